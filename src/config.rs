@@ -1,9 +1,10 @@
 use x11::xlib;
 use x11::keysym::*;
 
-use { Layout, Key, Button, Arg };
+use { Layout, Key, Button, Arg, Click };
+use status::modules::{ Module, Source };
 use wm::workspace::{ tileArrange, monocleArrange, noArrange, gridArrange };
-use { spawn, quit, changeWs, moveClientToWs, closeClient };
+use { spawn, quit, restart, changeWs, cycleWs, moveClientToWs, closeClient, toggleBar, toggleTopBar };
 
 /// Fonts (the first one available is used)
 pub const fonts: [&str; 1] = ["Fixed:size=11"];
@@ -26,6 +27,31 @@ pub const showbar: bool = true;
 pub const topbar: bool = true;
 // Bar time formatting
 pub const timeFormat: &str = "%H:%M:%S - %d %b %Y";
+/// Show the time (formatted with timeFormat) when no status text is set on the root window
+pub const showclock: bool = true;
+
+/// Roots of the sysfs and procfs filesystems, read by the status modules
+pub const sysfsroot: &str = "/sys";
+pub const procfsroot: &str = "/proc";
+/// Command printing the level and mute state of the sound card, read by the volume module
+pub const volumeCmd: &str = "amixer get Master";
+/// Separator between the status modules, and between the clickable blocks of the status text
+pub const statusSeparator: &str = " | ";
+/// Command run on clicks on a status block, with BLOCK_INDEX, BLOCK_BUTTON and BLOCK_TEXT in its
+/// environment (e.g. Some("dwm-rust-blocks"))
+pub const statusBlockCmd: Option<&str> = None;
+/// Show the status text on the bar of every monitor (false means only on the primary monitor)
+pub const statusAllMons: bool = false;
+/// Status modules (refresh interval in seconds, format), shown before the clock when no status
+/// text is set on the root window
+pub const statusModules: [Module; 6] = [
+    Module { source: Source::Cpu,     interval: 2,  format: "CPU {usage}%" },
+    Module { source: Source::Memory,  interval: 5,  format: "MEM {used}/{total}" },
+    Module { source: Source::Load,    interval: 5,  format: "{load1} {load5} {load15}" },
+    Module { source: Source::Network, interval: 2,  format: "NET {down}/s {up}/s" },
+    Module { source: Source::Volume,  interval: 2,  format: "VOL {level}% {muted}" },
+    Module { source: Source::Battery, interval: 30, format: "BAT {capacity}% {status}" }
+];
 
 /// Ratio of master area to stack area width
 pub const mfact: f32 = 0.5;
@@ -47,13 +73,16 @@ pub const tags: [&str; 9] = ["1", "2", "3", "4", "5", "6", "7", "8", "9"];
 pub const MODKEY: u32 = xlib::Mod4Mask;
 
 /// Key combinations and their actions
-pub const keys: [Key; 25] = [
+pub const keys: [Key; 28] = [
     //    modifier              key                 function                argument
     Key { modif:MODKEY,                 keysym:XK_Return as u64, func:spawn, arg:Arg {s: "terminator"}},
     Key { modif:MODKEY,                 keysym:XK_d as u64, func:spawn, arg:Arg {s: "rofi -show run"}},
     Key { modif:MODKEY|xlib::ShiftMask, keysym:XK_e as u64, func:quit, arg:Arg {i: 0}},
+    Key { modif:MODKEY|xlib::ShiftMask, keysym:XK_r as u64, func:restart, arg:Arg {i: 0}},
 
     Key { modif:MODKEY|xlib::ShiftMask, keysym:XK_q as u64,        func:closeClient, arg:Arg {i: 0}},
+    Key { modif:MODKEY,                 keysym:XK_b as u64,        func:toggleBar, arg:Arg {i: 0}},
+    Key { modif:MODKEY|xlib::ShiftMask, keysym:XK_b as u64,        func:toggleTopBar, arg:Arg {i: 0}},
 
     // Change WS
     Key { modif:MODKEY,                 keysym:XK_1 as u64, func:changeWs, arg:Arg {u: 1}},
@@ -82,13 +111,15 @@ pub const keys: [Key; 25] = [
     Key { modif:MODKEY|xlib::ShiftMask, keysym:XF86XK_AudioMute as u64, func:spawn, arg:Arg {s: "amixer -q sset 'Master' "}},
 ];
 
-/// Buttons and their actions
-pub const buttons: [Button; 0] = [
-
+/// Buttons and their actions (a click on a tag with argument 0 passes the index of the tag)
+pub const buttons: [Button; 3] = [
+    //       click                      mask       button                function          argument
+    Button { click:Click::ClkTagBar,     mask:0,    button:xlib::Button1, func:changeWs,    arg:Arg {u: 0}},
+    Button { click:Click::ClkTagBar,     mask:0,    button:xlib::Button4, func:cycleWs,     arg:Arg {i: -1}},
+    Button { click:Click::ClkTagBar,     mask:0,    button:xlib::Button5, func:cycleWs,     arg:Arg {i: 1}},
 ];
 
 /// Commands to execute at start of the wm
-pub const startCmds: [&str; 2] = [
-    "feh --bg-scale /home/vertmo/Images/Wallpapers/botw.png",
-    "statusbar"
+pub const startCmds: [&str; 1] = [
+    "feh --bg-scale /home/vertmo/Images/Wallpapers/botw.png"
 ];
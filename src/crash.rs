@@ -0,0 +1,82 @@
+extern crate x11;
+
+use std::ptr;
+use std::panic;
+use std::process;
+use std::sync::{ Mutex, TryLockError };
+
+use x11::xlib;
+
+use wm::WM;
+
+/**
+ * Position of a managed window, as known after the last handled event
+ */
+#[derive(Clone)]
+struct TrackedClient {
+    win: xlib::Window,
+    x: i32, y: i32,
+    oldbw: u32
+}
+
+/// Managed windows, on every workspace
+static CLIENTS: Mutex<Vec<TrackedClient>> = Mutex::new(Vec::new());
+
+/**
+ * Installs the panic hook and the X IO error handler, which give back every managed window
+ * (even those of hidden workspaces) before the process exits
+ */
+pub fn install() {
+    let defaulthook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        defaulthook(info);
+        restoreClients();
+    }));
+    unsafe { xlib::XSetIOErrorHandler(Some(xioerror)) };
+}
+
+/**
+ * Records the managed windows of the WM, so that they can be restored after a crash
+ */
+pub fn track(wm: &WM) {
+    let clients = wm.wss.iter().flat_map(|ws| { ws.clients.iter() }).map(|c| {
+        TrackedClient { win: c.win, x: c.x, y: c.y, oldbw: c.oldbw }
+    }).collect();
+    match CLIENTS.lock() {
+        Ok(mut tracked) => *tracked = clients,
+        Err(poisoned) => *poisoned.into_inner() = clients
+    }
+}
+
+/**
+ * Puts every managed window back on the screen and maps it. A new connection to the X server is
+ * used, since the one of the WM may be broken (or in the middle of a request).
+ */
+pub fn restoreClients() {
+    let clients = match CLIENTS.try_lock() {
+        Ok(tracked) => tracked.clone(),
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().clone(),
+        Err(TryLockError::WouldBlock) => return
+    };
+    let dpy = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if dpy.is_null() {
+        eprintln!("dwm-rust: cannot restore windows: can't open display");
+        return;
+    }
+    unsafe {
+        for c in clients.iter() {
+            xlib::XMoveWindow(dpy, c.win, c.x, c.y);
+            xlib::XSetWindowBorderWidth(dpy, c.win, c.oldbw);
+            xlib::XMapWindow(dpy, c.win);
+        }
+        xlib::XSync(dpy, 0);
+        xlib::XCloseDisplay(dpy);
+    }
+}
+
+/// Handles the loss of the connection to the X server
+extern "C" fn xioerror(_dpy: *mut xlib::Display) -> i32 {
+    eprintln!("dwm-rust: fatal IO error on the X connection");
+    restoreClients();
+    process::exit(1);
+}
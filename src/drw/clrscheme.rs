@@ -8,36 +8,62 @@ use x11::{ xlib, xft, xrender };
 /**
  * Stores a color (wrapper around the xft::XftColor struct)
  */
+#[derive(Clone, Copy)]
 pub struct Clr {
     pub pix: u64,
     pub rgb: xft::XftColor
 }
 
 /**
- * Creates a new color
+ * Creates a new color, or returns None if it cannot be allocated
  */
-pub fn createClr(dpy: &mut xlib::Display, screen: i32, clrname: &str) -> Clr {
+pub fn tryCreateClr(dpy: &mut xlib::Display, screen: i32, clrname: &str) -> Option<Clr> {
     let mut rgb = xft::XftColor {
         pixel: 0,
         color: xrender::XRenderColor { red: 0, green: 0, blue: 0, alpha: 0 }
     };
+    let name = match CString::new(clrname) {
+        Ok(name) => name,
+        Err(_) => return None
+    };
     if unsafe { xft::XftColorAllocName(dpy,
                                        xlib::XDefaultVisual(dpy, screen),
                                        xlib::XDefaultColormap(dpy, screen),
-                                       CString::new(clrname).unwrap().as_ptr(),
+                                       name.as_ptr(),
                                        &mut rgb) } == 0 {
-        eprintln!("Error, cannot allocate color {:?}\n", clrname);
-        process::exit(1)
+        None
+    } else {
+        Some(Clr {
+            pix: rgb.pixel,
+            rgb
+        })
     }
-    Clr {
-        pix: rgb.pixel,
-        rgb: rgb
+}
+
+/**
+ * Creates a new color (exits if it cannot be allocated)
+ */
+pub fn createClr(dpy: &mut xlib::Display, screen: i32, clrname: &str) -> Clr {
+    match tryCreateClr(dpy, screen, clrname) {
+        Some(clr) => clr,
+        None => {
+            eprintln!("Error, cannot allocate color {:?}\n", clrname);
+            process::exit(1)
+        }
     }
 }
 
+/**
+ * Frees a color
+ */
+pub fn freeClr(mut clr: Clr, dpy: &mut xlib::Display, screen: i32) {
+    unsafe { xft::XftColorFree(dpy, xlib::XDefaultVisual(dpy, screen), xlib::XDefaultColormap(dpy, screen), &mut clr.rgb) };
+}
+
 /**
  * Stores a color scheme (foreground, background and border colors)
  */
+#[derive(Clone, Copy)]
 pub struct ClrScheme {
     pub fg: Clr,
     pub bg: Clr,
@@ -54,3 +80,12 @@ pub fn createClrScheme(fg: Clr, bg: Clr, border: Clr) -> ClrScheme {
         border
     }
 }
+
+/**
+ * Frees the colors of a colorScheme
+ */
+pub fn freeClrScheme(scheme: ClrScheme, dpy: &mut xlib::Display, screen: i32) {
+    freeClr(scheme.fg, dpy, screen);
+    freeClr(scheme.bg, dpy, screen);
+    freeClr(scheme.border, dpy, screen);
+}
@@ -1,6 +1,9 @@
 extern crate x11;
+extern crate fontconfig;
 
+use std::ptr;
 use std::process;
+use std::collections::HashMap;
 use std::ffi::CString;
 
 use x11::{ xlib, xft, xrender };
@@ -21,9 +24,13 @@ pub struct Fnt {
     pub descent: i32,
     pub h: u32,
     pub xfont: *mut xft::XftFont,
-    pub pattern: *mut xft::FcPattern
+    pub pattern: *mut xft::FcPattern,  // Null for the fonts opened from a pattern
+    extents: HashMap<String, u32>  // Widths of the texts measured with this font
 }
 
+/// Number of text widths kept by each font (the cache is emptied when it is full)
+const EXTENTSCACHESIZE: usize = 512;
+
 impl PartialEq for Fnt {
     fn eq(&self, other: &Fnt) -> bool {
         self.xfont == other.xfont
@@ -31,9 +38,10 @@ impl PartialEq for Fnt {
 }
 
 /**
- * Add a new font
+ * Add a new font, from its name or from a pattern returned by XftFontMatch (the font then owns
+ * the pattern)
  */
-pub fn createFont(dpy: &mut xlib::Display, screen: i32, fontname: Option<&str>, fontpattern: Option<xft::FcPattern>) -> Option<Fnt>{
+pub fn createFont(dpy: &mut xlib::Display, screen: i32, fontname: Option<&str>, fontpattern: Option<*mut xft::FcPattern>) -> Option<Fnt>{
     if let Some(ftn) = fontname {
         let ftn_c = CString::new(ftn).unwrap();
         let xfont = unsafe { xft::XftFontOpenName(dpy, screen, ftn_c.as_ptr()) };
@@ -51,15 +59,16 @@ pub fn createFont(dpy: &mut xlib::Display, screen: i32, fontname: Option<&str>,
                         ascent: (*xfont).ascent,
                         descent: (*xfont).descent,
                         h: ((*xfont).ascent + (*xfont).descent) as u32,
-                        xfont: xfont,
-                        pattern: pattern
+                        xfont,
+                        pattern,
+                        extents: HashMap::new()
                     })
                 }
             }
         }
-    } else if let Some(mut ftp) = fontpattern {
-        let xfont = unsafe { xft::XftFontOpenPattern(dpy, &mut ftp) };
-        if !xfont.is_null() {
+    } else if let Some(ftp) = fontpattern {
+        let xfont = unsafe { xft::XftFontOpenPattern(dpy, ftp) };
+        if xfont.is_null() {
             eprintln!("error, cannot load font pattern\n");
             None
         } else {
@@ -69,7 +78,8 @@ pub fn createFont(dpy: &mut xlib::Display, screen: i32, fontname: Option<&str>,
                     descent: (*xfont).descent,
                     h: ((*xfont).ascent + (*xfont).descent) as u32,
                     xfont: xfont,
-                    pattern: &mut ftp
+                    pattern: ptr::null_mut(),
+                    extents: HashMap::new()
                 })
             }
         }
@@ -80,19 +90,47 @@ pub fn createFont(dpy: &mut xlib::Display, screen: i32, fontname: Option<&str>,
 }
 
 /**
- * Destructor (frees xfont)
+ * Destructor (frees xfont and pattern)
  */
 pub fn freeFnt(fnt: Fnt, dpy: &mut xlib::Display) {
+    if !fnt.pattern.is_null() {
+        unsafe { fontconfig::fontconfig::FcPatternDestroy(fnt.pattern as *mut fontconfig::fontconfig::FcPattern) };
+    }
     unsafe { xft::XftFontClose(dpy, fnt.xfont) };
 }
 
-pub fn getexts(fnt: &Fnt, dpy: &mut xlib::Display, text: Vec<u8>, tex: &mut Extnts) {
+/**
+ * Checks if the font has a glyph for a char
+ */
+pub fn hasChar(fnt: &Fnt, dpy: &mut xlib::Display, c: char) -> bool {
+    unsafe { xft::XftCharExists(dpy, fnt.xfont, c as u32) != 0 }
+}
+
+/**
+ * Width of a text drawn with this font, without using the cache
+ */
+pub fn textWidth(fnt: &Fnt, dpy: &mut xlib::Display, text: &str) -> u32 {
     let mut ext = xrender::XGlyphInfo { // Dummy value
         height: 0, width: 0, x: 0, y: 0, xOff: 0, yOff: 0
     };
     unsafe { xft::XftTextExtentsUtf8(dpy, fnt.xfont, text.as_ptr(), text.len() as i32, &mut ext) }
+    ext.xOff as u32
+}
+
+/**
+ * Extents of a text drawn with this font (widths are cached)
+ */
+pub fn getexts(fnt: &mut Fnt, dpy: &mut xlib::Display, text: &str, tex: &mut Extnts) {
     tex.h = fnt.h;
-    tex.w = ext.xOff as u32;
+    if let Some(&w) = fnt.extents.get(text) {
+        tex.w = w;
+        return;
+    }
+    tex.w = textWidth(fnt, dpy, text);
+    if fnt.extents.len() >= EXTENTSCACHESIZE {
+        fnt.extents.clear();
+    }
+    fnt.extents.insert(text.to_string(), tex.w);
 }
 
 // pub fn getexts_width(&mut self, dpy: &mut xlib::Display, text: Vec<u8>) -> u32 {
@@ -1,14 +1,17 @@
 extern crate x11;
 extern crate libc;
+extern crate fontconfig;
 
 use std::ptr;
+use std::ffi::CString;
+use std::os::raw::c_char;
 
 use x11::{ xlib, xft };
 
 pub mod clrscheme;
 pub mod fnt;
 
-use self::clrscheme::ClrScheme;
+use self::clrscheme::{ Clr, ClrScheme };
 use self::fnt::Fnt;
 
 /**
@@ -27,6 +30,13 @@ pub fn createCur(drw: &mut Drw, shape: u32) -> Cur {
     }
 }
 
+/**
+ * Frees a cursor
+ */
+pub fn freeCur(cur: Cur, dpy: &mut xlib::Display) {
+    unsafe { xlib::XFreeCursor(dpy, cur.cursor) };
+}
+
 /**
  * Stores a drawble area (related to a Display)
  */
@@ -39,9 +49,23 @@ pub struct Drw<'a> {
     drawable: xlib::Drawable,
     gc: xlib::GC,
     scheme: *const ClrScheme,
-    pub fonts: Vec<Fnt>
+    pub fonts: Vec<Fnt>,  // Configured fonts, then the fallback fonts found by fontconfig
+    nomatches: Vec<char>,  // Chars without any font
+    clrs: Vec<(String, Clr)>,  // Colors allocated by name
+    icons: Vec<(String, Option<Icon>)>  // Icons loaded from XBM files
 }
 
+/**
+ * Stores an icon (a bitmap of depth 1)
+ */
+struct Icon {
+    pixmap: xlib::Pixmap,
+    w: u32, h: u32
+}
+
+/// Value returned by XReadBitmapFile on success
+const BitmapSuccess: i32 = 0;
+
 /**
  * Creates a drawable area for a display
  */
@@ -55,25 +79,38 @@ pub fn createDrw(dpy: &mut xlib::Display, screen: i32, root: xlib::Window, w: u3
         drawable: 0,
         gc: ptr::null_mut(),
         fonts: Vec::new(),
-        scheme: ptr::null_mut()
+        nomatches: Vec::new(),
+        scheme: ptr::null_mut(),
+        clrs: Vec::new(),
+        icons: Vec::new()
     };
     drw.drawable = unsafe { xlib::XCreatePixmap(drw.dpy, root, w, h, xlib::XDefaultDepth(drw.dpy, screen) as u32) };
     drw.gc = unsafe { xlib::XCreateGC(drw.dpy, root, 0, ptr::null_mut()) };
     drw
 }
 
-// /**
-//  * Destructor
-//  */
-// pub fn freeDrw(&mut self) {
-//     for f in &mut self.fonts {
-//         f.free(self.dpy);
-//     }
-//     unsafe {
-//         xlib::XFreePixmap(self.dpy, self.drawable);
-//         xlib::XFreeGC(self.dpy, self.gc);
-//     }
-// }
+/**
+ * Destructor (frees fonts, colors, icons, pixmap and GC), gives back the display
+ */
+pub fn freeDrw<'a>(drw: Drw<'a>) -> &'a mut xlib::Display {
+    let Drw { dpy, drawable, gc, fonts, screen, clrs, icons, .. } = drw;
+    for f in fonts {
+        fnt::freeFnt(f, dpy);
+    }
+    for (_, c) in clrs {
+        clrscheme::freeClr(c, dpy, screen);
+    }
+    for (_, i) in icons {
+        if let Some(i) = i {
+            unsafe { xlib::XFreePixmap(dpy, i.pixmap) };
+        }
+    }
+    unsafe {
+        xlib::XFreePixmap(dpy, drawable);
+        xlib::XFreeGC(dpy, gc);
+    }
+    dpy
+}
 
 /**
  * Changes the color scheme
@@ -94,6 +131,105 @@ pub fn loadFonts<'a>(mut drw: Drw<'a>, fontnames: Vec<&str>) -> Drw<'a> {
     drw
 }
 
+/// Number of colors kept allocated by trimCaches (the least recently used ones are freed first)
+const CLRCACHESIZE: usize = 64;
+/// Number of icons kept loaded by trimCaches (the least recently used ones are freed first)
+const ICONCACHESIZE: usize = 32;
+
+/**
+ * Gets a color from its name, allocating it the first time (it stays allocated until the next
+ * trimCaches)
+ */
+pub fn getClr<'a>(mut drw: Drw<'a>, name: &str) -> (Drw<'a>, Option<Clr>) {
+    if let Some(i) = drw.clrs.iter().position(|(n, _)| { n == name }) {
+        // Most recently used colors are at the end
+        let entry = drw.clrs.remove(i);
+        let c = entry.1;
+        drw.clrs.push(entry);
+        return (drw, Some(c));
+    }
+    let c = clrscheme::tryCreateClr(drw.dpy, drw.screen, name);
+    if let Some(c) = c {
+        drw.clrs.push((name.to_string(), c));
+    }
+    (drw, c)
+}
+
+/**
+ * Loads an icon from an XBM file, the first time it is used (it stays loaded until the next
+ * trimCaches)
+ */
+fn loadIcon<'a>(mut drw: Drw<'a>, path: &str) -> (Drw<'a>, usize) {
+    if let Some(i) = drw.icons.iter().position(|(p, _)| { p == path }) {
+        // Most recently used icons are at the end
+        let entry = drw.icons.remove(i);
+        drw.icons.push(entry);
+        let i = drw.icons.len() - 1;
+        return (drw, i);
+    }
+    let mut w = 0; let mut h = 0;
+    let mut pixmap = 0;
+    let mut xhot = 0; let mut yhot = 0;
+    let cpath = CString::new(path).unwrap_or(CString::new("").unwrap());
+    let icon = if unsafe { xlib::XReadBitmapFile(drw.dpy, drw.root, cpath.as_ptr(), &mut w, &mut h, &mut pixmap, &mut xhot, &mut yhot) } == BitmapSuccess {
+        Some(Icon { pixmap, w, h })
+    } else {
+        eprintln!("dwm-rust: cannot read icon {}", path);
+        None
+    };
+    drw.icons.push((path.to_string(), icon));
+    let i = drw.icons.len() - 1;
+    (drw, i)
+}
+
+/**
+ * Frees the least recently used colors and icons beyond the cache sizes. Must not be called while
+ * a color got from getClr is still in use
+ */
+pub fn trimCaches(mut drw: Drw) -> Drw {
+    let n = drw.clrs.len().saturating_sub(CLRCACHESIZE);
+    for (_, c) in drw.clrs.drain(..n) {
+        clrscheme::freeClr(c, drw.dpy, drw.screen);
+    }
+    let n = drw.icons.len().saturating_sub(ICONCACHESIZE);
+    for (_, i) in drw.icons.drain(..n) {
+        if let Some(i) = i {
+            unsafe { xlib::XFreePixmap(drw.dpy, i.pixmap) };
+        }
+    }
+    drw
+}
+
+/**
+ * Width of an icon (0 if it cannot be read)
+ */
+pub fn iconw<'a>(path: &str, drw: Drw<'a>) -> (Drw<'a>, u32) {
+    let (drw, i) = loadIcon(drw, path);
+    let w = drw.icons[i].1.as_ref().map(|icon| { icon.w }).unwrap_or(0);
+    (drw, w)
+}
+
+/**
+ * Draws an icon, vertically centered in an area of height h, and returns its width
+ */
+pub fn icon<'a>(drw: Drw<'a>, x: i32, y: i32, h: u32, path: &str) -> (Drw<'a>, u32) {
+    let (drw, i) = loadIcon(drw, path);
+    let s = drw.scheme;
+    let w = match drw.icons[i].1 {
+        Some(ref icon) if !s.is_null() => {
+            unsafe {
+                xlib::XSetForeground(drw.dpy, drw.gc, (*s).fg.pix);
+                xlib::XSetBackground(drw.dpy, drw.gc, (*s).bg.pix);
+                xlib::XCopyPlane(drw.dpy, icon.pixmap, drw.drawable, drw.gc, 0, 0, icon.w, icon.h, x, y + (h as i32 - icon.h as i32) / 2, 1);
+            }
+            icon.w
+        },
+        Some(ref icon) => icon.w,
+        None => 0
+    };
+    (drw, w)
+}
+
 /**
  * Draws a rectangle
  */
@@ -115,56 +251,171 @@ pub fn rect(drw: Drw, x: i32, y: i32, w: u32, h: u32, filled: bool, invert: bool
     drw
 }
 
+/// Fontconfig properties used to find fallback fonts
+const FC_CHARSET: &[u8] = b"charset\0";
+const FC_SCALABLE: &[u8] = b"scalable\0";
+
 /**
- * Draws text, and returns text width
+ * Finds a font containing c with fontconfig, from the pattern of the first font
  */
-pub fn text<'a>(drw: Drw<'a>, mut x: i32, y: i32, mut w:u32, h:u32, text: &str, invert: bool) -> (Drw<'a>, i32) {
-    let s = drw.scheme;
-    let mut d = ptr::null_mut();
-    if !s.is_null() {
-        if drw.fonts.len() > 0 {
-            let render = x!= 0 || y != 0 || w != 0 || h != 0;
-            if !render {
-                w = !w;
+fn fallbackFont(drw: &mut Drw, c: char) -> Option<Fnt> {
+    use self::fontconfig::fontconfig as fc;
+    if drw.fonts[0].pattern.is_null() {
+        return None;
+    }
+    let mut result = xft::FcResult::NoMatch;
+    let fcmatch = unsafe {
+        let charset = fc::FcCharSetCreate();
+        fc::FcCharSetAddChar(charset, c as u32);
+        let pattern = fc::FcPatternDuplicate(drw.fonts[0].pattern as *const fc::FcPattern);
+        fc::FcPatternAddCharSet(pattern, FC_CHARSET.as_ptr() as *const c_char, charset);
+        fc::FcPatternAddBool(pattern, FC_SCALABLE.as_ptr() as *const c_char, 1);
+        fc::FcConfigSubstitute(ptr::null_mut(), pattern, fc::FcMatchPattern);
+        fc::FcDefaultSubstitute(pattern);
+        let fcmatch = xft::XftFontMatch(drw.dpy, drw.screen, pattern as *const xft::FcPattern, &mut result);
+        fc::FcCharSetDestroy(charset);
+        fc::FcPatternDestroy(pattern);
+        fcmatch
+    };
+    if fcmatch.is_null() {
+        return None;
+    }
+    match fnt::createFont(drw.dpy, drw.screen, None, Some(fcmatch)) {
+        Some(f) => {
+            if fnt::hasChar(&f, drw.dpy, c) {
+                Some(f)
             } else {
-                if invert {
-                    unsafe { xlib::XSetForeground(drw.dpy, drw.gc, (*s).fg.pix) };
-                } else {
-                    unsafe { xlib::XSetForeground(drw.dpy, drw.gc, (*s).bg.pix) };
-                }
-                unsafe { xlib::XFillRectangle(drw.dpy, drw.drawable, drw.gc, x, y, w, h) };
-                d = unsafe { xft:: XftDrawCreate(drw.dpy, drw.drawable, xlib::XDefaultVisual(drw.dpy, drw.screen), xlib::XDefaultColormap(drw.dpy, drw.screen)) };
+                fnt::freeFnt(f, drw.dpy);
+                None
             }
+        },
+        None => {
+            unsafe { fc::FcPatternDestroy(fcmatch as *mut fc::FcPattern) };
+            None
+        }
+    }
+}
 
-            let curfont = &drw.fonts[0];
-            let mut charexists = false;
-            let mut tex = fnt::Extnts { // Dummy value
-                w: 0, h: 0
-            };
-            loop {
-                let utf8str = text.as_bytes();
-                fnt::getexts(curfont, drw.dpy, utf8str.to_vec(), &mut tex);
-
-                if render {
-                    let th = curfont.ascent + curfont.descent;
-                    let ty = y + (h / 2) as i32 - (th / 2) + curfont.ascent;
-                    let tx = x + (h / 2) as i32;
-                    if invert {
-                        unsafe { xft::XftDrawStringUtf8(d, &(*s).bg.rgb, curfont.xfont, tx, ty, utf8str.as_ptr(), utf8str.len() as i32) };
-                    } else {
-                        unsafe { xft::XftDrawStringUtf8(d, &(*s).fg.rgb, curfont.xfont, tx, ty, utf8str.as_ptr(), utf8str.len() as i32) };
-                    }
-                }
-                x += tex.w as i32;
-                w -= tex.w;
-
-                if !charexists /* || nextfont != curfont*/ {
-                    break;
-                } else {
-                    charexists = false;
-                }
-            }
+/**
+ * Index of the font used to draw c : the first loaded font containing it, or a fallback font
+ * (loaded the first time), or the first font if no font contains it
+ */
+fn fontFor<'a>(mut drw: Drw<'a>, c: char) -> (Drw<'a>, usize) {
+    let found = {
+        let Drw { ref mut dpy, ref fonts, .. } = drw;
+        fonts.iter().position(|f| { fnt::hasChar(f, dpy, c) })
+    };
+    if let Some(i) = found {
+        return (drw, i);
+    }
+    if drw.nomatches.contains(&c) {
+        return (drw, 0);
+    }
+    match fallbackFont(&mut drw, c) {
+        Some(f) => {
+            drw.fonts.push(f);
+            let i = drw.fonts.len() - 1;
+            (drw, i)
+        },
+        None => {
+            drw.nomatches.push(c);
+            (drw, 0)
+        }
+    }
+}
+
+/**
+ * Splits a text into runs of chars drawn with the same font : (font index, start, end)
+ */
+fn fontRuns<'a>(drw: Drw<'a>, text: &str) -> (Drw<'a>, Vec<(usize, usize, usize)>) {
+    text.char_indices().fold((drw, Vec::new()), |(drw, mut runs), (i, c)| {
+        let (drw, f) = fontFor(drw, c);
+        let end = i + c.len_utf8();
+        match runs.last_mut() {
+            Some(&mut (rf, _, ref mut rend)) if rf == f => *rend = end,
+            _ => runs.push((f, i, end))
+        }
+        (drw, runs)
+    })
+}
+
+/// Appended to truncated texts
+const ELLIPSIS: &str = "...";
+
+/**
+ * Draws a string with one font and color, vertically centered in an area of height h
+ */
+fn drawString(d: *mut xft::XftDraw, curfont: &Fnt, clr: &xft::XftColor, x: i32, y: i32, h: u32, text: &str) {
+    let th = curfont.ascent + curfont.descent;
+    let ty = y + (h / 2) as i32 - (th / 2) + curfont.ascent;
+    unsafe { xft::XftDrawStringUtf8(d, clr, curfont.xfont, x, ty, text.as_ptr(), text.len() as i32) };
+}
+
+/**
+ * Draws text (lpad pixels from the left of the area), and returns text width. A text wider than
+ * the area is truncated (on a char boundary) and ends with an ellipsis.
+ */
+#[allow(clippy::too_many_arguments)] // Same arguments as drw_text in dwm
+pub fn text<'a>(drw: Drw<'a>, mut x: i32, y: i32, mut w:u32, h:u32, lpad: u32, text: &str, invert: bool) -> (Drw<'a>, i32) {
+    let s = drw.scheme;
+    if s.is_null() || drw.fonts.is_empty() {
+        return (drw, x);
+    }
+    let mut d = ptr::null_mut();
+    let clr = if invert { unsafe { &(*s).bg.rgb } } else { unsafe { &(*s).fg.rgb } };
+    let render = x!= 0 || y != 0 || w != 0 || h != 0;
+    if !render {
+        w = !w;
+    } else {
+        if invert {
+            unsafe { xlib::XSetForeground(drw.dpy, drw.gc, (*s).fg.pix) };
+        } else {
+            unsafe { xlib::XSetForeground(drw.dpy, drw.gc, (*s).bg.pix) };
+        }
+        unsafe { xlib::XFillRectangle(drw.dpy, drw.drawable, drw.gc, x, y, w, h) };
+        d = unsafe { xft:: XftDrawCreate(drw.dpy, drw.drawable, xlib::XDefaultVisual(drw.dpy, drw.screen), xlib::XDefaultColormap(drw.dpy, drw.screen)) };
+        w = w.saturating_sub(2 * lpad);
+    }
+
+    let (mut drw, runs) = fontRuns(drw, text);
+    let mut tex = fnt::Extnts { // Dummy value
+        w: 0, h: 0
+    };
+    // Measure the runs, to know if the text has to be truncated
+    let widths: Vec<u32> = runs.iter().map(|&(f, start, end)| {
+        fnt::getexts(&mut drw.fonts[f], drw.dpy, &text[start..end], &mut tex);
+        tex.w
+    }).collect();
+    let ellipsisw = if widths.iter().sum::<u32>() > w {
+        fnt::getexts(&mut drw.fonts[0], drw.dpy, ELLIPSIS, &mut tex);
+        if tex.w <= w { tex.w } else { 0 }
+    } else { 0 };
+    let truncated = ellipsisw > 0;
+    w -= ellipsisw;
+
+    for ((f, start, end), tw) in runs.into_iter().zip(widths) {
+        // Truncate the run (on a char boundary) until it fits. The prefixes are not cached, as
+        // each of them would take a place in the cache.
+        let mut end = end;
+        tex.w = tw;
+        while tex.w > w && end > start {
+            end = text[start..end].char_indices().last().map(|(i, _)| start + i).unwrap_or(start);
+            tex.w = fnt::textWidth(&drw.fonts[f], drw.dpy, &text[start..end]);
         }
+        if render && end > start {
+            drawString(d, &drw.fonts[f], clr, x + lpad as i32, y, h, &text[start..end]);
+        }
+        x += tex.w as i32;
+        w -= tex.w;
+        if tex.w < tw {
+            break; // No room left for the next runs
+        }
+    }
+    if truncated {
+        if render {
+            drawString(d, &drw.fonts[0], clr, x + lpad as i32, y, h, ELLIPSIS);
+        }
+        x += ellipsisw as i32;
     }
     if !d.is_null() {
         unsafe { xft::XftDrawDestroy(d) };
@@ -176,9 +427,19 @@ pub fn text<'a>(drw: Drw<'a>, mut x: i32, y: i32, mut w:u32, h:u32, text: &str,
  * Width of a text
  */
 pub fn textw<'a>(s: &str, drw: Drw<'a>) -> (Drw<'a>, u32) {
-    let (drw, w) = text(drw, 0, 0, 0, 0, s, false);
+    if drw.fonts.is_empty() {
+        return (drw, 0);
+    }
+    let (mut drw, runs) = fontRuns(drw, s);
+    let mut tex = fnt::Extnts { // Dummy value
+        w: 0, h: 0
+    };
+    let w = runs.into_iter().fold(0, |w, (f, start, end)| {
+        fnt::getexts(&mut drw.fonts[f], drw.dpy, &s[start..end], &mut tex);
+        w + tex.w
+    });
     let h = drw.fonts[0].h;
-    (drw, w as u32 + h)
+    (drw, w + h)
 }
 
 /**
@@ -187,7 +448,6 @@ pub fn textw<'a>(s: &str, drw: Drw<'a>) -> (Drw<'a>, u32) {
 pub fn mapWindow(drw: Drw, win: xlib::Window, x: i32, y: i32, w: u32, h: u32) -> Drw {
     unsafe {
         xlib::XCopyArea(drw.dpy, drw.drawable, win, drw.gc, x, y, w, h, x, y);
-        xlib::XSync(drw.dpy, 0);
     }
     drw
 }
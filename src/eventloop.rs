@@ -0,0 +1,89 @@
+extern crate libc;
+
+use std::time::{ Duration, Instant };
+
+use wm::WM;
+
+/**
+ * Stores a timer : func is called once, when the deadline is reached
+ */
+pub struct Timer {
+    pub deadline: Instant,
+    pub func: for<'b> fn (WM<'b>) -> WM<'b>
+}
+
+/**
+ * Stores a file descriptor watched by the main loop : func is called when it becomes readable
+ */
+pub struct FdWatch {
+    pub fd: i32,
+    pub func: for<'b> fn (WM<'b>, i32) -> WM<'b>
+}
+
+/**
+ * Calls func after delay (timers are one-shot, a periodic task adds its timer again)
+ */
+pub fn addTimer<'a>(mut wm: WM<'a>, delay: Duration, func: for<'b> fn (WM<'b>) -> WM<'b>) -> WM<'a> {
+    wm.timers.push(Timer { deadline: Instant::now() + delay, func });
+    wm
+}
+
+/**
+ * Calls func each time fd becomes readable
+ */
+pub fn watchFd<'a>(mut wm: WM<'a>, fd: i32, func: for<'b> fn (WM<'b>, i32) -> WM<'b>) -> WM<'a> {
+    wm.watches.push(FdWatch { fd, func });
+    wm
+}
+
+/**
+ * Stops watching fd
+ */
+pub fn unwatchFd<'a>(mut wm: WM<'a>, fd: i32) -> WM<'a> {
+    wm.watches.retain(|w| { w.fd != fd });
+    wm
+}
+
+/**
+ * Calls the functions of the timers whose deadline is reached
+ */
+pub fn fireTimers<'a>(mut wm: WM<'a>) -> WM<'a> {
+    let now = Instant::now();
+    let (expired, pending): (Vec<Timer>, Vec<Timer>) = wm.timers.drain(..).partition(|t| { t.deadline <= now });
+    wm.timers = pending;
+    expired.into_iter().fold(wm, |wm, t| { (t.func)(wm) })
+}
+
+/**
+ * Time (in milliseconds, rounded up) to wait before the next deadline, or -1 if there is no timer
+ */
+pub fn pollTimeout(wm: &WM) -> i32 {
+    let now = Instant::now();
+    match wm.timers.iter().map(|t| { t.deadline }).min() {
+        Some(deadline) if deadline > now => {
+            let d = deadline - now;
+            (d.as_secs() * 1000 + (d.subsec_nanos() as u64).div_ceil(1_000_000)).min(i32::MAX as u64) as i32
+        },
+        Some(_) => 0,
+        None => -1
+    }
+}
+
+/**
+ * Poll entries for the watched file descriptors
+ */
+pub fn pollFds(wm: &WM) -> Vec<libc::pollfd> {
+    wm.watches.iter().map(|w| { libc::pollfd { fd: w.fd, events: libc::POLLIN, revents: 0 } }).collect()
+}
+
+/**
+ * Calls the functions watching the file descriptors which became readable (or were closed)
+ */
+pub fn dispatchFds<'a>(wm: WM<'a>, fds: &[libc::pollfd]) -> WM<'a> {
+    fds.iter().filter(|p| { p.revents != 0 }).fold(wm, |wm, p| {
+        match wm.watches.iter().find(|w| { w.fd == p.fd }).map(|w| { w.func }) {
+            Some(func) => func(wm, p.fd),
+            None => wm
+        }
+    })
+}
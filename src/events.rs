@@ -6,6 +6,13 @@ use x11::xlib;
 use wm;
 use wm::WM;
 use wm::client;
+use wm::workspace;
+use wm::monitor;
+use status::markup;
+use status::markup::Item;
+
+use { Arg, Click, spawnWithEnv };
+use { WITHDRAWNSTATE, NETWMNAME };
 
 use config;
 
@@ -19,10 +26,12 @@ pub fn handleEvent<'a>(wm: WM<'a>, ev: &xlib::XEvent) -> WM<'a> {
             xlib::ConfigureNotify => configureNotify(wm, ev),
             //xlib::EnterNotify => enternotify(wm, ev),
             xlib::DestroyNotify => destroyNotify(wm, ev),
+            xlib::Expose => expose(wm, ev),
             xlib::KeyPress => keyPress(wm, ev),
             xlib::ButtonPress => buttonPress(wm, ev),
             xlib::MapRequest => mapRequest(wm, ev),
             xlib::PropertyNotify => propertyNotify(wm, ev),
+            xlib::UnmapNotify => unmapNotify(wm, ev),
             // TODO : les autres handlers
             _ => wm
         }
@@ -35,7 +44,10 @@ pub fn handleEvent<'a>(wm: WM<'a>, ev: &xlib::XEvent) -> WM<'a> {
 pub fn configureRequest<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
     let ev = unsafe { e.configure_request };
     if let Some(c) = client::findFromWindow(ev.window, &wm.wss) {
-        client::configure(c, wm.drw.dpy);
+        // Clients of hidden workspaces stay out of the screen
+        if monitor::showing(&wm.mons, c.wsindex).is_some() {
+            client::configure(c, wm.drw.dpy);
+        }
     } else {
         let mut wc = xlib::XWindowChanges {
             x: ev.x, y: ev.y,
@@ -46,7 +58,6 @@ pub fn configureRequest<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
         };
         unsafe { xlib::XConfigureWindow(wm.drw.dpy, ev.window, ev.value_mask as u32, &mut wc) };
     }
-    unsafe { xlib::XSync(wm.drw.dpy, 0) };
     wm
 }
 
@@ -95,11 +106,30 @@ pub fn configureNotify<'a>(wm : WM<'a>, e: &xlib::XEvent) -> WM<'a> {
  */
 pub fn destroyNotify<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
     let ev = unsafe { e.destroy_window };
-    wm::updateStatus(wm::unManage(wm, ev.window))
+    wm::updateStatus(wm::unManage(wm, ev.window, true))
 }
 
-fn cleanmask(mask: u32) -> u32 {
-    mask
+/**
+ * Handles an Expose event : draws again the bar whose content was lost
+ */
+pub fn expose<'a>(mut wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
+    let ev = unsafe { e.expose };
+    if ev.count != 0 {
+        return wm;
+    }
+    if let Some(m) = wm.mons.iter().position(|m| { m.barwin == ev.window }) {
+        wm.mons[m].bar = workspace::createBarCache();
+        wm::updateStatus(wm)
+    } else {
+        wm
+    }
+}
+
+/**
+ * Removes the NumLock and CapsLock modifiers from a mask
+ */
+fn cleanmask(numlockmask: u32, mask: u32) -> u32 {
+    mask & !(numlockmask|xlib::LockMask) & (xlib::ShiftMask|xlib::ControlMask|xlib::Mod1Mask|xlib::Mod2Mask|xlib::Mod3Mask|xlib::Mod4Mask|xlib::Mod5Mask)
 }
 
 /**
@@ -110,7 +140,7 @@ pub fn keyPress<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
     let keysym = unsafe { xlib::XKeycodeToKeysym(wm.drw.dpy, ev.keycode as u8, 0) };
     for i in 0..config::keys.len() {
         if keysym == config::keys[i].keysym
-        && cleanmask(ev.state) == cleanmask(config::keys[i].modif) {
+        && cleanmask(wm.numlockmask, ev.state) == cleanmask(wm.numlockmask, config::keys[i].modif) {
             let func = config::keys[i].func;
             return func(&config::keys[i].arg, wm);
         }
@@ -119,12 +149,57 @@ pub fn keyPress<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
 }
 
 /**
- * Handles a button press
+ * Handles a button press : finds what was clicked, and calls the matching function of config::buttons
  */
 pub fn buttonPress<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
-    let _ev = unsafe { e.button };
-    // TODO
-    wm
+    let ev = unsafe { e.button };
+    let (wm, click, index) = if let Some(m) = wm.mons.iter().position(|m| { m.barwin == ev.window }) {
+        // Clicking on the bar of a monitor selects it
+        let wm = if m != wm.selmonindex { wm::updateStatus(wm::focus(wm::selectMon(wm, m), None)) } else { wm };
+        let status = if m == 0 || config::statusAllMons { Some(&wm.stextitems) } else { None };
+        let (drw, click, index) = workspace::barClick(wm.drw, &wm.wss, &wm.mons[m], &wm.stext, status, ev.x);
+        (WM { drw, ..wm }, click, index)
+    } else if let Some(wsindex) = client::findFromWindow(ev.window, &wm.wss).map(|c| { c.wsindex }) {
+        // Focus the client (and its monitor), and give it the click
+        let wm = match monitor::showing(&wm.mons, wsindex) {
+            Some(m) if m != wm.selmonindex => wm::selectMon(wm, m),
+            _ => wm
+        };
+        let wm = wm::updateStatus(wm::focus(wm, Some(ev.window)));
+        unsafe { xlib::XAllowEvents(wm.drw.dpy, xlib::ReplayPointer, xlib::CurrentTime) };
+        (wm, Click::ClkClientWin, None)
+    } else {
+        // Clicking on the root window selects the monitor under the pointer
+        let m = monitor::at(&wm.mons, ev.x_root, ev.y_root);
+        let wm = if m != wm.selmonindex { wm::updateStatus(wm::focus(wm::selectMon(wm, m), None)) } else { wm };
+        (wm, Click::ClkRootWin, None)
+    };
+    for b in config::buttons.iter() {
+        if b.click == click && b.button == ev.button
+        && cleanmask(wm.numlockmask, b.mask) == cleanmask(wm.numlockmask, ev.state) {
+            let func = b.func;
+            // A click on a tag without argument passes the (1-based) index of the tag
+            return match index {
+                Some(tag) if click == Click::ClkTagBar && unsafe { b.arg.u } == 0 => func(&Arg { u: tag as u32 + 1 }, wm),
+                _ => func(&b.arg, wm)
+            };
+        }
+    }
+    // Other clicks on a status block run config::statusBlockCmd
+    match (click, index, config::statusBlockCmd) {
+        (Click::ClkStatusText, Some(block), Some(cmd)) => {
+            // The command gets the text of the block, without its markup
+            let items = markup::parse(wm.stext.split(config::statusSeparator).nth(block).unwrap_or(""));
+            let text: String = items.iter().filter_map(|item| {
+                match *item {
+                    Item::Text(ref t) => Some(t.as_str()),
+                    _ => None
+                }
+            }).collect();
+            spawnWithEnv(cmd, &[("BLOCK_INDEX", block.to_string()), ("BLOCK_BUTTON", ev.button.to_string()), ("BLOCK_TEXT", text)], wm)
+        },
+        _ => wm
+    }
 }
 
 /**
@@ -138,7 +213,8 @@ pub fn mapRequest<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
     if unsafe { xlib::XGetWindowAttributes(wm.drw.dpy, ev.window, &mut wa) } == 0 || wa.override_redirect != 0 {
         wm
     } else if client::findFromWindow(ev.window, &wm.wss) == None {
-        return wm::manage(wm, ev.window, wa);
+        let wsindex = wm.selwsindex;
+        wm::manage(wm, ev.window, wa, wsindex)
     } else {
         wm
     }
@@ -149,5 +225,29 @@ pub fn mapRequest<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
  */
 pub fn propertyNotify<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
     let ev = unsafe { e.property };
-    if ev.window == wm.root { wm::updateStatus(wm) } else { wm }
+    if ev.window == wm.root {
+        wm::updateStatus(wm)
+    } else if ev.state != xlib::PropertyDelete && (ev.atom == xlib::XA_WM_NAME || ev.atom == wm.netatom[NETWMNAME]) {
+        wm::updateStatus(wm::updateClientTitle(wm, ev.window))
+    } else {
+        wm
+    }
+}
+
+/**
+ * Handles an UnmapNotify event : the client withdrew its window
+ */
+pub fn unmapNotify<'a>(wm: WM<'a>, e: &xlib::XEvent) -> WM<'a> {
+    let ev = unsafe { e.unmap };
+    if ev.send_event != 0 {
+        // Synthetic event (ICCCM 4.1.4) : the real unmap follows
+        if let Some(c) = client::findFromWindow(ev.window, &wm.wss) {
+            client::setState(c, wm.drw.dpy, &wm.wmatom, WITHDRAWNSTATE);
+        }
+        wm
+    } else if client::findFromWindow(ev.window, &wm.wss).is_some() {
+        wm::updateStatus(wm::unManage(wm, ev.window, false))
+    } else {
+        wm
+    }
 }
@@ -3,13 +3,16 @@
 #![allow(dead_code)]
 
 extern crate x11;
+extern crate libc;
 
 use std::env;
 use std::process;
 use std::ptr;
+use std::ffi::CString;
 use std::process::Command;
+use std::os::unix::process::CommandExt;
 
-use x11::{ xlib, xinerama };
+use x11::xlib;
 
 /// Events handling
 pub mod events;
@@ -19,11 +22,20 @@ pub mod wm;
 pub mod drw;
 /// Configuration module
 pub mod config;
+/// Crash safety module
+pub mod crash;
+/// Unix signals handling
+pub mod signals;
+/// Timers and file descriptors of the main loop
+pub mod eventloop;
+/// Built-in status sources
+pub mod status;
 
 use events::handleEvent;
 
 use wm::WM;
 use wm::workspace;
+use wm::monitor;
 use wm::workspace::Layout;
 use wm::client;
 use wm::client::Client;
@@ -33,7 +45,9 @@ const VERSION: &str = "0.0.1";
 // WM Atom indexes
 const WMPROTOCOLS: usize = 0; const WMDELETE: usize = 1; const WMSTATE: usize  = 2; const WMTAKEFOCUS: usize = 3; const WMLAST: usize = 4;
 // Net Atom indexes
-const NETACTIVEWINDOW: usize = 0; const NETSUPPORTED: usize = 1; const NETWMNAME: usize = 2; const NETWMSTATE: usize = 3; const NETWMFULLSCREEN: usize = 4; const NETWMWINDOWTYPE: usize = 5; const NETWMWINDOWTYPEDIALOG: usize = 6; const NETCLIENTLIST: usize = 7; const NETLAST: usize = 8;
+const NETACTIVEWINDOW: usize = 0; const NETSUPPORTED: usize = 1; const NETWMNAME: usize = 2; const NETWMSTATE: usize = 3; const NETWMFULLSCREEN: usize = 4; const NETWMWINDOWTYPE: usize = 5; const NETWMWINDOWTYPEDIALOG: usize = 6; const NETCLIENTLIST: usize = 7; const NETWMCHECK: usize = 8; const NETWMDESKTOP: usize = 9; const NETLAST: usize = 10;
+// ICCCM window states
+pub const WITHDRAWNSTATE: i64 = 0; pub const NORMALSTATE: i64 = 1; pub const ICONICSTATE: i64 = 3;
 // Cursor indexes
 pub const CURNORMAL: usize = 0; pub const CURRESIZE: usize = 1; pub const CURMOVE: usize = 2;
 // Color scheme indexes
@@ -62,6 +76,7 @@ pub struct Key<'a> {
 /**
  * Different types of click events
  */
+#[derive(PartialEq, Clone, Copy)]
 pub enum Click {
     ClkTagBar, ClkLtSymbol, ClkStatusText, ClkWinTitle, ClkClientWin, ClkRootWin, ClkLast
 }
@@ -87,7 +102,7 @@ pub struct Pertag<'a> {
     selltds: Vec<u32>,  // Selected layouts
     ltidxs: Vec<Vec<&'a Layout<'a>>>, // Matrix of tags and layouts
     showbars: Vec<bool>,    // Display bar for each tag
-    prefzooms: Vec<&'a Client<'a>> // Zoom information
+    prefzooms: Vec<&'a Client> // Zoom information
 }
 
 fn main() {
@@ -104,8 +119,17 @@ fn main() {
     } if let Some(dpy) = Some( unsafe { &mut(*xlib::XOpenDisplay(ptr::null())) }) {
         // This is where we'll work
         checkOtherWm(dpy);
-        let wm = cleanup(run(setup(dpy)));
-        unsafe { xlib::XCloseDisplay(wm.drw.dpy) };
+        crash::install();
+        signals::install();
+        let wm = run(setup(dpy));
+        let restarting = wm.restart;
+        let dpy = cleanup(wm);
+        unsafe { xlib::XCloseDisplay(dpy) };
+        if restarting {
+            let err = Command::new(&args[0]).exec();
+            eprintln!("dwm-rust: cannot restart: {}", err);
+            process::exit(1);
+        }
     } else {
         println!("dwm-rust: can't open display");
         process::exit(1);
@@ -127,6 +151,7 @@ unsafe extern "C" fn xerror(_dpy: *mut xlib::Display, ee: *mut xlib::XErrorEvent
         0
     } else {
         eprintln!("dwm-rust: fatal error: request code={}, error code={}", (*ee).request_code, (*ee).error_code);
+        crash::restoreClients();
         process::exit(1);
     }
 }
@@ -158,9 +183,11 @@ pub fn setup(dpy: &mut xlib::Display) -> WM {
         process::exit(1);
     }
 
-    let wm = wm::updateStatus(wm::updateBars(wm::createWorkspaces(wm::initWm(drw, screen, root, sw, sh))));
+    let wm = wm::updateStatus(wm::updateBars(wm::createWorkspaces(wm::createMonitors(wm::initWm(drw, screen, root, sw, sh)))));
+    // The NumLock mask (computed by grabKeys) is needed to grab the buttons of the scanned windows
+    let wm = wm::updateStatus(wm::scan(wm::grabKeys(createWmCheckWin(wm))));
     unsafe {
-        xlib::XChangeProperty(wm.drw.dpy, wm.root, wm.netatom[NETSUPPORTED], xlib::XA_ATOM, 32, xlib::PropModeReplace, &(wm.netatom[0] as u8), NETLAST as i32);
+        xlib::XChangeProperty(wm.drw.dpy, wm.root, wm.netatom[NETSUPPORTED], xlib::XA_ATOM, 32, xlib::PropModeReplace, wm.netatom.as_ptr() as *const u8, NETLAST as i32);
         xlib::XDeleteProperty(wm.drw.dpy, wm.root, wm.netatom[NETCLIENTLIST]);
         xlib::XChangeWindowAttributes(wm.drw.dpy, wm.root, xlib::CWEventMask|xlib::CWCursor, &mut xlib::XSetWindowAttributes {
             background_pixmap: 0,
@@ -181,16 +208,22 @@ pub fn setup(dpy: &mut xlib::Display) -> WM {
         });
     }
     // focus(None); TODO
-    executeStartCmds(wm::setRootBackground(wm::grabKeys(wm)))
+    status::start(executeStartCmds(wm::setRootBackground(wm)))
 }
 
-pub fn isUniqueGeom(unique: &Vec<xinerama::XineramaScreenInfo>, n: usize, info: &xinerama::XineramaScreenInfo) -> bool {
-    for i in n..0 {
-        if unique[i].x_org == info.x_org && unique[i].y_org == info.y_org && unique[i].width == info.width && unique[i].height == info.height {
-            return false
-        }
+/**
+ * Creates the supporting window used by clients to detect an EWMH compliant window manager
+ */
+fn createWmCheckWin(wm: WM) -> WM {
+    let utf8string = unsafe { xlib::XInternAtom(wm.drw.dpy, CString::new("UTF8_STRING").unwrap().as_ptr(), 0) };
+    let wmcheckwin = unsafe { xlib::XCreateSimpleWindow(wm.drw.dpy, wm.root, 0, 0, 1, 1, 0, 0, 0) };
+    let wmname = "dwm-rust";
+    unsafe {
+        xlib::XChangeProperty(wm.drw.dpy, wmcheckwin, wm.netatom[NETWMCHECK], xlib::XA_WINDOW, 32, xlib::PropModeReplace, &wmcheckwin as *const u64 as *const u8, 1);
+        xlib::XChangeProperty(wm.drw.dpy, wmcheckwin, wm.netatom[NETWMNAME], utf8string, 8, xlib::PropModeReplace, wmname.as_ptr(), wmname.len() as i32);
+        xlib::XChangeProperty(wm.drw.dpy, wm.root, wm.netatom[NETWMCHECK], xlib::XA_WINDOW, 32, xlib::PropModeReplace, &wmcheckwin as *const u64 as *const u8, 1);
     }
-    true
+    WM { wmcheckwin, ..wm }
 }
 
 pub fn executeStartCmds(wm: WM) -> WM {
@@ -198,32 +231,77 @@ pub fn executeStartCmds(wm: WM) -> WM {
 }
 
 /**
- * Main program loop
+ * Main program loop : waits for X events, signals, timers and watched file descriptors
  */
 pub fn run(mut wm: WM) -> WM {
     let ev = &mut xlib::XEvent { any: xlib::XAnyEvent { type_: 0, serial: 0, send_event: 0, display: wm.drw.dpy, window: wm.root } }; // Dummy value
-    unsafe {
-        xlib::XSync(wm.drw.dpy, 0);
-        while wm.running && xlib::XNextEvent(wm.drw.dpy, ev) == 0 {
+    let xfd = unsafe { xlib::XConnectionNumber(wm.drw.dpy) };
+    unsafe { xlib::XSync(wm.drw.dpy, 0) };
+    crash::track(&wm);
+    while wm.running {
+        wm = eventloop::fireTimers(signals::handleSignals(wm));
+        // XPending also flushes the requests before we wait
+        while wm.running && unsafe { xlib::XPending(wm.drw.dpy) } > 0 {
+            unsafe { xlib::XNextEvent(wm.drw.dpy, ev) };
             wm = handleEvent(wm, ev);
         }
+        if !wm.running {
+            break;
+        }
+        // The clients are tracked and the bars are drawn once for the whole batch of events
+        crash::track(&wm);
+        if wm.redraw {
+            wm = wm::refreshBars(wm);
+        }
+        unsafe { xlib::XFlush(wm.drw.dpy) };
+        // Events read during the redraw are already queued : poll would not see them
+        if unsafe { xlib::XQLength(wm.drw.dpy) } > 0 {
+            continue;
+        }
+        let mut fds = vec![
+            libc::pollfd { fd: xfd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: signals::fd(), events: libc::POLLIN, revents: 0 }
+        ];
+        fds.extend(eventloop::pollFds(&wm));
+        let timeout = eventloop::pollTimeout(&wm);
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } > 0 {
+            wm = eventloop::dispatchFds(wm, &fds[2..]);
+        }
     }
     wm
 }
 /**
- * Executes a shell command
+ * Executes a shell command (through /bin/sh)
  *
  * # Arguments
  * * `arg` - Reference to an Arg containing the command (&str) to execute
  * * `wm` - Window Manager
  */
 pub fn spawn<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
-    let v : Vec<&str> = unsafe { arg.s.split(' ').collect() };
-    let mut command = Command::new(v[0]);
-    for i in 1..v.len() {
-        command.arg(v[i]);
+    spawnWithEnv(unsafe { arg.s }, &[], wm)
+}
+
+/**
+ * Executes a shell command (through /bin/sh), with some more environment variables
+ */
+pub fn spawnWithEnv<'a>(cmd: &str, env: &[(&str, String)], wm: WM<'a>) -> WM<'a> {
+    let xfd = unsafe { xlib::XConnectionNumber(wm.drw.dpy) };
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg(cmd);
+    for &(name, ref value) in env.iter() {
+        command.env(name, value);
+    }
+    // The child gets its own session, and must not keep our connection to the X server
+    unsafe {
+        command.pre_exec(move || {
+            libc::close(xfd);
+            libc::setsid();
+            Ok(())
+        });
+    }
+    if let Err(err) = command.spawn() {
+        eprintln!("dwm-rust: cannot execute '{}': {}", cmd, err);
     }
-    command.spawn().expect(&["Command", unsafe { arg.s }, "has failed..."].join(" ")[..]);
     wm
 }
 
@@ -237,18 +315,25 @@ pub fn spawn<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
 pub fn changeWs<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
     let index = unsafe { arg.u };
     if index > 0 && index <= wm.wss.len() as u32 && (index-1) != wm.selwsindex as u32 {
-        workspace::hideAllClients(&wm.wss[wm.selwsindex], wm.drw.dpy);
-        let wm = wm::updateStatus(WM {
-            selwsindex: (index-1) as usize,
-            ..wm
-        });
-        workspace::showAllClients(&wm.wss[wm.selwsindex], wm.drw.dpy);
-        wm
+        wm::updateStatus(wm::focus(wm::view(wm, (index-1) as usize), None))
     } else {
         wm
     }
 }
 
+/**
+ * Cycles through the Workspaces
+ *
+ * # Arguments
+ * * `arg` - Reference to an Arg containing the offset (i32) to the Workspace to switch to
+ * * `wm` - Window Manager
+ */
+pub fn cycleWs<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
+    let n = wm.wss.len() as i32;
+    let index = ((wm.selwsindex as i32 + unsafe { arg.i }) % n + n) % n;
+    changeWs(&Arg { u: index as u32 + 1 }, wm)
+}
+
 /**
  * Moves a Client to another Workspace
  *
@@ -263,8 +348,10 @@ pub fn moveClientToWs<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
         {
             for i in 0..wm.wss[wm.selwsindex].clients.len() {
                 if wm.wss[wm.selwsindex].clients[i].win == w {
-                    let c = wm.wss[wm.selwsindex].clients.remove(i);
+                    let c = Client { wsindex: index, ..wm.wss[wm.selwsindex].clients.remove(i) };
+                    client::setDesktop(&c, wm.drw.dpy, &wm.netatom);
                     wm.wss[index].clients.insert(0, c);
+                    wm.wss[index].sel = Some(w);
                     break;
                 }
             }
@@ -272,14 +359,50 @@ pub fn moveClientToWs<'a>(arg: &Arg, wm: WM<'a>) -> WM<'a> {
         let ws = workspace::updateGeom(wm.wss.remove(wm.selwsindex), wm.drw.dpy);
         wm.wss.insert(wm.selwsindex, ws);
         let ws = workspace::updateGeom(wm.wss.remove(index), wm.drw.dpy);
-        workspace::hideAllClients(&ws, wm.drw.dpy);
+        if monitor::showing(&wm.mons, index).is_some() {
+            workspace::showAllClients(&ws, wm.drw.dpy, &wm.wmatom);
+        } else {
+            workspace::hideAllClients(&ws, wm.drw.dpy, &wm.wmatom);
+        }
         wm.wss.insert(index, ws);
-        wm::updateStatus(wm)
+        wm::updateStatus(wm::focus(wm, None))
     } else {
         wm
     }
 }
 
+/**
+ * Shows or hides the bar of the current Workspace
+ *
+ * # Arguments
+ * * `arg` - Reference to an Arg containing whatever
+ * * `wm` - Window Manager
+ */
+pub fn toggleBar<'a>(_: &Arg, mut wm: WM<'a>) -> WM<'a> {
+    let ws = wm.wss.remove(wm.selwsindex);
+    let showbar = !ws.showbar;
+    let ws = workspace::updateBarPos(workspace::Workspace { showbar, ..ws }, wm.bh);
+    let ws = workspace::updateGeom(ws, wm.drw.dpy);
+    wm.wss.insert(wm.selwsindex, ws);
+    wm::updateStatus(wm::updateBarWin(wm))
+}
+
+/**
+ * Moves the bar of the current Workspace between the top and the bottom of the screen
+ *
+ * # Arguments
+ * * `arg` - Reference to an Arg containing whatever
+ * * `wm` - Window Manager
+ */
+pub fn toggleTopBar<'a>(_: &Arg, mut wm: WM<'a>) -> WM<'a> {
+    let ws = wm.wss.remove(wm.selwsindex);
+    let topbar = !ws.topbar;
+    let ws = workspace::updateBarPos(workspace::Workspace { topbar, ..ws }, wm.bh);
+    let ws = workspace::updateGeom(ws, wm.drw.dpy);
+    wm.wss.insert(wm.selwsindex, ws);
+    wm::updateStatus(wm::updateBarWin(wm))
+}
+
 /**
  * Closes a Client
  *
@@ -314,9 +437,44 @@ pub fn quit<'a>(_: &Arg, wm: WM<'a>) -> WM<'a> {
 }
 
 /**
- * Cleanup and frees memory
+ * Restarts the WM in place, keeping the state of the clients
  */
-fn cleanup(wm: WM) -> WM {
-    // TODO
-    wm
+pub fn restart<'a>(_: &Arg, wm: WM<'a>) -> WM<'a> {
+    WM {
+        running: false,
+        restart: true,
+        ..wm::saveState(wm)
+    }
+}
+
+/**
+ * Cleanup and frees memory, gives back the display
+ */
+fn cleanup<'a>(wm: WM<'a>) -> &'a mut xlib::Display {
+    unsafe { xlib::XUngrabKey(wm.drw.dpy, xlib::AnyKey, xlib::AnyModifier, wm.root) };
+    // Give back every client, even those of hidden workspaces
+    for ws in wm.wss.iter() {
+        for c in ws.clients.iter() {
+            client::release(c, wm.drw.dpy, &wm.wmatom);
+        }
+    }
+    unsafe {
+        for m in wm.mons.iter().filter(|m| { m.barwin != 0 }) {
+            xlib::XUnmapWindow(wm.drw.dpy, m.barwin);
+            xlib::XDestroyWindow(wm.drw.dpy, m.barwin);
+        }
+        xlib::XDestroyWindow(wm.drw.dpy, wm.wmcheckwin);
+        xlib::XDeleteProperty(wm.drw.dpy, wm.root, wm.netatom[NETWMCHECK]);
+        xlib::XSync(wm.drw.dpy, 0);
+        xlib::XSetInputFocus(wm.drw.dpy, xlib::PointerRoot as u64, xlib::RevertToPointerRoot, xlib::CurrentTime);
+        xlib::XDeleteProperty(wm.drw.dpy, wm.root, wm.netatom[NETACTIVEWINDOW]);
+    }
+    let WM { drw, cursor, scheme, screen, .. } = wm;
+    for c in cursor {
+        drw::freeCur(c, drw.dpy);
+    }
+    for s in scheme {
+        drw::clrscheme::freeClrScheme(s, drw.dpy, screen);
+    }
+    drw::freeDrw(drw)
 }
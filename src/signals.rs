@@ -0,0 +1,81 @@
+extern crate libc;
+
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{ AtomicBool, AtomicI32, Ordering };
+
+use wm::WM;
+use { Arg, quit, restart };
+
+/// Self-pipe written by the signal handler, to wake up the main loop
+static PIPEREAD: AtomicI32 = AtomicI32::new(-1);
+static PIPEWRITE: AtomicI32 = AtomicI32::new(-1);
+
+/// Signals received and not handled yet
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static RESTART: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Signal handler : only does async-signal-safe things (reaping children, setting flags and
+ * writing to the self-pipe)
+ */
+extern "C" fn onSignal(sig: libc::c_int) {
+    unsafe {
+        let errno = *libc::__errno_location();
+        match sig {
+            libc::SIGCHLD => while libc::waitpid(-1, ptr::null_mut(), libc::WNOHANG) > 0 {},
+            libc::SIGTERM | libc::SIGINT => TERMINATE.store(true, Ordering::SeqCst),
+            libc::SIGHUP => RESTART.store(true, Ordering::SeqCst),
+            _ => ()
+        }
+        libc::write(PIPEWRITE.load(Ordering::SeqCst), b"s".as_ptr() as *const libc::c_void, 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+/**
+ * Creates the self-pipe and installs the handlers for SIGCHLD, SIGTERM, SIGINT and SIGHUP
+ */
+pub fn install() {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } == -1 {
+        eprintln!("dwm-rust: cannot create the signal pipe");
+    }
+    PIPEREAD.store(fds[0], Ordering::SeqCst);
+    PIPEWRITE.store(fds[1], Ordering::SeqCst);
+    for &sig in [libc::SIGCHLD, libc::SIGTERM, libc::SIGINT, libc::SIGHUP].iter() {
+        unsafe {
+            let mut sa: libc::sigaction = mem::zeroed();
+            sa.sa_sigaction = onSignal as extern "C" fn(libc::c_int) as usize;
+            sa.sa_flags = libc::SA_RESTART | if sig == libc::SIGCHLD { libc::SA_NOCLDSTOP } else { 0 };
+            libc::sigemptyset(&mut sa.sa_mask);
+            if libc::sigaction(sig, &sa, ptr::null_mut()) == -1 {
+                eprintln!("dwm-rust: cannot install handler for signal {}", sig);
+            }
+        }
+    }
+    // Reap the children which may have terminated before
+    while unsafe { libc::waitpid(-1, ptr::null_mut(), libc::WNOHANG) } > 0 {}
+}
+
+/**
+ * File descriptor which becomes readable when a signal is received
+ */
+pub fn fd() -> i32 {
+    PIPEREAD.load(Ordering::SeqCst)
+}
+
+/**
+ * Handles the signals received since the last call : quits on SIGTERM or SIGINT, restarts on SIGHUP
+ */
+pub fn handleSignals<'a>(wm: WM<'a>) -> WM<'a> {
+    let mut buf = [0u8; 64];
+    while unsafe { libc::read(fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+    if TERMINATE.swap(false, Ordering::SeqCst) {
+        quit(&Arg { i: 0 }, wm)
+    } else if RESTART.swap(false, Ordering::SeqCst) {
+        restart(&Arg { i: 0 }, wm)
+    } else {
+        wm
+    }
+}
@@ -0,0 +1,155 @@
+/**
+ * Items of a status text written with markup. Commands are enclosed in carets :
+ * * `^c#rrggbb^` : foreground color
+ * * `^b#rrggbb^` : background color
+ * * `^s<name>^` : colors of a scheme (`norm` or `sel`)
+ * * `^d^` : back to the default colors
+ * * `^r<x>,<y>,<w>,<h>^` : filled rectangle with the foreground color, relative to the current position
+ *   (does not move the position)
+ * * `^f<px>^` : moves the position forward
+ * * `^i<path>^` : XBM icon, drawn with the current colors
+ * * `^^` : a caret
+ *
+ * Anything else is drawn as text.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub enum Item {
+    Text(String),
+    Fg(String),
+    Bg(String),
+    Scheme(String),
+    Reset,
+    Rect(i32, i32, u32, u32),
+    Forward(i32),
+    Icon(String)
+}
+
+/**
+ * Parses a command (the text between two carets)
+ */
+fn parseCommand(cmd: &str) -> Option<Item> {
+    let mut chars = cmd.chars();
+    let c = chars.next()?;
+    let arg = chars.as_str();
+    match c {
+        'c' if !arg.is_empty() => Some(Item::Fg(arg.to_string())),
+        'b' if !arg.is_empty() => Some(Item::Bg(arg.to_string())),
+        's' if !arg.is_empty() => Some(Item::Scheme(arg.to_string())),
+        'd' if arg.is_empty() => Some(Item::Reset),
+        'r' => {
+            let v: Vec<&str> = arg.split(',').map(|s| { s.trim() }).collect();
+            if v.len() != 4 { return None }
+            Some(Item::Rect(v[0].parse().ok()?, v[1].parse().ok()?, v[2].parse().ok()?, v[3].parse().ok()?))
+        },
+        'f' => Some(Item::Forward(arg.trim().parse().ok()?)),
+        'i' if !arg.is_empty() => Some(Item::Icon(arg.to_string())),
+        _ => None
+    }
+}
+
+/**
+ * Adds some text to the items, merging it with the previous text
+ */
+fn pushText(mut items: Vec<Item>, text: &str) -> Vec<Item> {
+    if text.is_empty() {
+        return items;
+    }
+    if let Some(&mut Item::Text(ref mut prev)) = items.last_mut() {
+        prev.push_str(text);
+        return items;
+    }
+    items.push(Item::Text(text.to_string()));
+    items
+}
+
+/**
+ * Parses a status text
+ */
+pub fn parse(stext: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut rest = stext;
+    while let Some(start) = rest.find('^') {
+        items = pushText(items, &rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('^') {
+            items = pushText(items, "^");
+            rest = escaped;
+            continue;
+        }
+        match after.find('^') {
+            Some(end) => {
+                match parseCommand(&after[..end]) {
+                    Some(item) => items.push(item),
+                    None => items = pushText(items, &rest[start..start + end + 2]) // Not a command
+                }
+                rest = &after[end + 1..];
+            },
+            None => {
+                items = pushText(items, &rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    pushText(items, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(t: &str) -> Item {
+        Item::Text(t.to_string())
+    }
+
+    #[test]
+    fn plainText() {
+        assert_eq!(parse("hello"), vec![text("hello")]);
+        assert_eq!(parse(""), vec![]);
+    }
+
+    #[test]
+    fn escapedCaret() {
+        assert_eq!(parse("a^^b"), vec![text("a^b")]);
+        assert_eq!(parse("^^^^"), vec![text("^^")]);
+    }
+
+    #[test]
+    fn unterminatedCommand() {
+        assert_eq!(parse("a^c#ff0000"), vec![text("a^c#ff0000")]);
+        assert_eq!(parse("a^"), vec![text("a^")]);
+    }
+
+    #[test]
+    fn unknownCommandsAreText() {
+        assert_eq!(parse("a^x1^b"), vec![text("a^x1^b")]);
+        assert_eq!(parse("^d1^"), vec![text("^d1^")]);
+        assert_eq!(parse("^c^"), vec![text("^c^")]);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse("^c#ff0000^red^d^"), vec![Item::Fg(String::from("#ff0000")), text("red"), Item::Reset]);
+        assert_eq!(parse("^b#000^^ssel^"), vec![Item::Bg(String::from("#000")), Item::Scheme(String::from("sel"))]);
+    }
+
+    #[test]
+    fn rectangles() {
+        assert_eq!(parse("^r0,1,10,5^"), vec![Item::Rect(0, 1, 10, 5)]);
+        assert_eq!(parse("^r-2, 3 ,4,5^"), vec![Item::Rect(-2, 3, 4, 5)]);
+        assert_eq!(parse("^r1,2,3^"), vec![text("^r1,2,3^")]);
+        assert_eq!(parse("^r1,2,-3,4^"), vec![text("^r1,2,-3,4^")]);
+        assert_eq!(parse("^ra,2,3,4^"), vec![text("^ra,2,3,4^")]);
+    }
+
+    #[test]
+    fn forward() {
+        assert_eq!(parse("a^f10^b"), vec![text("a"), Item::Forward(10), text("b")]);
+        assert_eq!(parse("^f-3^"), vec![Item::Forward(-3)]);
+        assert_eq!(parse("^fx^"), vec![text("^fx^")]);
+    }
+
+    #[test]
+    fn icons() {
+        assert_eq!(parse("^i/tmp/a.xbm^"), vec![Item::Icon(String::from("/tmp/a.xbm"))]);
+    }
+}
@@ -0,0 +1,110 @@
+extern crate libc;
+
+use std::ffi::{ CString, CStr };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use std::os::raw::c_char;
+use std::ptr;
+use std::time::Instant;
+
+use x11::xlib;
+
+use wm;
+use wm::WM;
+use eventloop;
+use config;
+
+/// Status modules (battery, cpu, memory, load, network)
+pub mod modules;
+/// Markup language of the status text
+pub mod markup;
+
+use self::modules::ModuleState;
+
+extern "C" {
+    fn strftime(s: *mut c_char, max: libc::size_t, format: *const c_char, tm: *const libc::tm) -> libc::size_t;
+}
+
+/**
+ * Formats the local time with config::timeFormat
+ */
+pub fn clockText() -> String {
+    let mut buf = [0 as c_char; 128];
+    let format = CString::new(config::timeFormat).unwrap();
+    unsafe {
+        let t = libc::time(ptr::null_mut());
+        let mut tm: libc::tm = ::std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null()
+        || strftime(buf.as_mut_ptr(), buf.len(), format.as_ptr(), &tm) == 0 {
+            return String::new();
+        }
+        CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+    }
+}
+
+/**
+ * Text of the status bar when no status has been set on the root window : the text of the status
+ * modules, followed by the clock
+ */
+pub fn defaultText(states: &[ModuleState]) -> String {
+    let mut blocks: Vec<String> = states.iter().map(|s| { s.text.clone() }).filter(|t| { !t.is_empty() }).collect();
+    if config::showclock {
+        blocks.push(clockText());
+    }
+    if blocks.is_empty() { String::from("dwm-rust") } else { blocks.join(config::statusSeparator) }
+}
+
+/**
+ * Does config::timeFormat show the seconds
+ */
+fn showsSeconds() -> bool {
+    ["%S", "%T", "%s", "%r", "%X", "%c"].iter().any(|f| { config::timeFormat.contains(f) })
+}
+
+/**
+ * Redraws the clock if no status has been set on the root window, and waits for the next second
+ * (or minute)
+ */
+pub fn clockTick(wm: WM) -> WM {
+    let wm = if wm::getTextProp(wm.drw.dpy, wm.root, xlib::XA_WM_NAME).is_none() { wm::updateStatus(wm) } else { wm };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    let delay = if showsSeconds() {
+        Duration::from_secs(1) - Duration::from_nanos(now.subsec_nanos() as u64)
+    } else {
+        Duration::from_secs(60 - now.as_secs() % 60) - Duration::from_nanos(now.subsec_nanos() as u64)
+    };
+    eventloop::addTimer(wm, delay, clockTick)
+}
+
+/**
+ * Updates the status modules whose refresh interval has elapsed, redraws them if no status has been
+ * set on the root window, and waits for the next module to refresh
+ */
+pub fn modulesTick(wm: WM) -> WM {
+    let now = Instant::now();
+    let wm = WM {
+        statusmodules: wm.statusmodules.into_iter().zip(config::statusModules.iter()).map(|(state, module)| {
+            if state.next <= now { modules::update(state, module) } else { state }
+        }).collect(),
+        ..wm
+    };
+    let wm = if wm::getTextProp(wm.drw.dpy, wm.root, xlib::XA_WM_NAME).is_none() { wm::updateStatus(wm) } else { wm };
+    match wm.statusmodules.iter().map(|s| { s.next }).min() {
+        Some(next) => {
+            let delay = next.duration_since(Instant::now());
+            eventloop::addTimer(wm, delay, modulesTick)
+        },
+        None => wm
+    }
+}
+
+/**
+ * Starts the built-in status sources enabled in the configuration
+ */
+pub fn start(wm: WM) -> WM {
+    let wm = WM {
+        statusmodules: config::statusModules.iter().map(|_| { modules::createModuleState() }).collect(),
+        ..wm
+    };
+    let wm = modulesTick(wm);
+    if config::showclock { clockTick(wm) } else { wm }
+}
@@ -0,0 +1,325 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{ Duration, Instant };
+
+use config;
+
+/**
+ * Sources of data for the status modules
+ */
+pub enum Source {
+    Battery, Cpu, Memory, Load, Network, Volume
+}
+
+/**
+ * Stores a status module : its source, refresh interval (in seconds) and format.
+ * In the format, `{name}` is replaced by the value `name` of the source :
+ * * Battery : `capacity`, `status`
+ * * Cpu : `usage`
+ * * Memory : `used`, `total`, `percent`
+ * * Load : `load1`, `load5`, `load15`
+ * * Network : `down`, `up` (per second)
+ * * Volume : `level` (in percent), `muted` (`muted` when the control is off, empty otherwise)
+ */
+pub struct Module<'a> {
+    pub source: Source,
+    pub interval: u64,
+    pub format: &'a str
+}
+
+/**
+ * Stores the current state of a status module
+ */
+pub struct ModuleState {
+    pub next: Instant,
+    pub text: String,
+    prev: Option<(u64, u64)>,
+    prevtime: Instant
+}
+
+/**
+ * Creates the state of a module, to be updated right away
+ */
+pub fn createModuleState() -> ModuleState {
+    let now = Instant::now();
+    ModuleState { next: now, text: String::new(), prev: None, prevtime: now }
+}
+
+fn readFile<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| { s.trim().to_string() })
+}
+
+/**
+ * Capacity (in percent) and status of the first battery found in the sysfs
+ */
+pub fn battery(sysfs: &str) -> Option<(u32, String)> {
+    let dir = Path::new(sysfs).join("class/power_supply");
+    let mut entries: Vec<_> = fs::read_dir(dir).ok()?.filter_map(|e| { e.ok() }).map(|e| { e.path() }).collect();
+    entries.sort();
+    let bat = entries.into_iter().find(|p| { readFile(p.join("type")) == Some(String::from("Battery")) })?;
+    let capacity = readFile(bat.join("capacity"))?.parse().ok()?;
+    Some((capacity, readFile(bat.join("status")).unwrap_or(String::from("Unknown"))))
+}
+
+/**
+ * Idle and total CPU time (in jiffies) since boot, from the procfs
+ */
+pub fn cpu(procfs: &str) -> Option<(u64, u64)> {
+    let stat = readFile(Path::new(procfs).join("stat"))?;
+    let line = stat.lines().find(|l| { l.starts_with("cpu ") })?;
+    let v: Vec<u64> = line.split_whitespace().skip(1).filter_map(|f| { f.parse().ok() }).collect();
+    if v.len() < 4 { return None }
+    let idle = v[3] + v.get(4).cloned().unwrap_or(0); // idle + iowait
+    // guest and guest_nice (after steal) are already counted in user and nice
+    Some((idle, v.iter().take(8).sum()))
+}
+
+/**
+ * Total and available memory (in bytes), from the procfs
+ */
+pub fn memory(procfs: &str) -> Option<(u64, u64)> {
+    let meminfo = readFile(Path::new(procfs).join("meminfo"))?;
+    let field = |name: &str| -> Option<u64> {
+        let line = meminfo.lines().find(|l| { l.starts_with(name) })?;
+        line.split_whitespace().nth(1)?.parse::<u64>().ok().map(|kb| { kb * 1024 })
+    };
+    Some((field("MemTotal:")?, field("MemAvailable:")?))
+}
+
+/**
+ * Load averages over 1, 5 and 15 minutes, from the procfs
+ */
+pub fn load(procfs: &str) -> Option<(String, String, String)> {
+    let loadavg = readFile(Path::new(procfs).join("loadavg"))?;
+    let v: Vec<&str> = loadavg.split_whitespace().collect();
+    if v.len() < 3 { return None }
+    Some((v[0].to_string(), v[1].to_string(), v[2].to_string()))
+}
+
+/**
+ * Bytes received and sent by all the interfaces (except loopback), from the procfs
+ */
+pub fn network(procfs: &str) -> Option<(u64, u64)> {
+    let dev = readFile(Path::new(procfs).join("net/dev"))?;
+    Some(dev.lines().skip(2).filter_map(|l| {
+        let mut parts = l.splitn(2, ':');
+        let iface = parts.next()?.trim();
+        let v: Vec<u64> = parts.next()?.split_whitespace().filter_map(|f| { f.parse().ok() }).collect();
+        if iface == "lo" || v.len() < 9 { None } else { Some((v[0], v[8])) }
+    }).fold((0, 0), |(rx, tx), (r, t)| { (rx + r, tx + t) }))
+}
+
+/**
+ * Level (in percent) and mute state of the first channel in the output of cmd (an `amixer get`
+ * command)
+ */
+pub fn volume(cmd: &str) -> Option<(u32, bool)> {
+    let output = Command::new("sh").arg("-c").arg(cmd).output().ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let line = output.lines().find(|l| { l.contains("%]") })?;
+    let start = line.find('[')? + 1;
+    let end = start + line[start..].find("%]")?;
+    Some((line[start..end].parse().ok()?, line.contains("[off]")))
+}
+
+/**
+ * Formats a size in bytes with a binary unit
+ */
+pub fn humanSize(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 || size >= 10.0 { format!("{:.0}{}", size, units[unit]) } else { format!("{:.1}{}", size, units[unit]) }
+}
+
+/**
+ * Replaces every `{name}` of format by its value
+ */
+pub fn fill(format: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(format.to_string(), |text, &(name, ref value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/**
+ * Reads the source of a module and formats its text (empty when the source is not available, or
+ * for Cpu and Network, until two samples have been read)
+ */
+pub fn update(state: ModuleState, module: &Module) -> ModuleState {
+    let now = Instant::now();
+    let elapsed = now.duration_since(state.prevtime);
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    let next = now + Duration::from_secs(module.interval.max(1));
+    let (text, prev) = match module.source {
+        Source::Battery => (battery(config::sysfsroot).map(|(capacity, status)| {
+            fill(module.format, &[("capacity", capacity.to_string()), ("status", status)])
+        }), None),
+        Source::Cpu => {
+            let cur = cpu(config::procfsroot);
+            let text = match (state.prev, cur) {
+                (Some((pidle, ptotal)), Some((idle, total))) if total > ptotal => {
+                    let usage = 100 * ((total - ptotal) - idle.saturating_sub(pidle).min(total - ptotal)) / (total - ptotal);
+                    Some(fill(module.format, &[("usage", usage.to_string())]))
+                },
+                _ => None
+            };
+            (text, cur)
+        },
+        Source::Memory => (memory(config::procfsroot).map(|(total, available)| {
+            let used = total - available.min(total);
+            let percent = (100 * used).checked_div(total).unwrap_or(0);
+            fill(module.format, &[("used", humanSize(used)), ("total", humanSize(total)), ("percent", percent.to_string())])
+        }), None),
+        Source::Load => (load(config::procfsroot).map(|(l1, l5, l15)| {
+            fill(module.format, &[("load1", l1), ("load5", l5), ("load15", l15)])
+        }), None),
+        Source::Network => {
+            let cur = network(config::procfsroot);
+            let text = match (state.prev, cur) {
+                (Some((prx, ptx)), Some((rx, tx))) if secs > 0.0 => {
+                    let down = (rx.saturating_sub(prx) as f64 / secs) as u64;
+                    let up = (tx.saturating_sub(ptx) as f64 / secs) as u64;
+                    Some(fill(module.format, &[("down", humanSize(down)), ("up", humanSize(up))]))
+                },
+                _ => None
+            };
+            (text, cur)
+        },
+        Source::Volume => (volume(config::volumeCmd).map(|(level, muted)| {
+            let muted = if muted { String::from("muted") } else { String::new() };
+            fill(module.format, &[("level", level.to_string()), ("muted", muted)]).trim_end().to_string()
+        }), None)
+    };
+    ModuleState {
+        next,
+        text: text.unwrap_or(String::new()),
+        prev,
+        prevtime: now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+
+    /**
+     * Creates a fixture directory with some files (paths relative to the directory)
+     */
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("dwm-rust-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for &(path, content) in files.iter() {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn batteryReadsTheFirstBattery() {
+        let root = fixture("battery", &[
+            ("class/power_supply/AC/type", "Mains\n"),
+            ("class/power_supply/BAT1/type", "Battery\n"),
+            ("class/power_supply/BAT1/capacity", "42\n"),
+            ("class/power_supply/BAT1/status", "Discharging\n"),
+            ("class/power_supply/BAT2/type", "Battery\n"),
+            ("class/power_supply/BAT2/capacity", "90\n")
+        ]);
+        assert_eq!(battery(root.to_str().unwrap()), Some((42, String::from("Discharging"))));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn batteryMissing() {
+        let root = fixture("nobattery", &[("class/power_supply/AC/type", "Mains\n")]);
+        assert_eq!(battery(root.to_str().unwrap()), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn cpuIgnoresGuestTime() {
+        let root = fixture("cpu", &[
+            ("stat", "cpu  10 20 30 400 50 6 7 8 100 200\ncpu0 1 2 3 4 5 6 7 8 9 10\n")
+        ]);
+        assert_eq!(cpu(root.to_str().unwrap()), Some((450, 531)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn memoryInBytes() {
+        let root = fixture("memory", &[
+            ("meminfo", "MemTotal:       2048 kB\nMemFree:         512 kB\nMemAvailable:   1024 kB\n")
+        ]);
+        assert_eq!(memory(root.to_str().unwrap()), Some((2048 * 1024, 1024 * 1024)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn loadAverages() {
+        let root = fixture("load", &[("loadavg", "0.50 1.25 2.00 1/123 4567\n")]);
+        assert_eq!(load(root.to_str().unwrap()), Some((String::from("0.50"), String::from("1.25"), String::from("2.00"))));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn networkIgnoresLoopback() {
+        let root = fixture("network", &[
+            ("net/dev", "Inter-|   Receive                                                |  Transmit\n\
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n\
+    lo:    1000      10    0    0    0     0          0         0     1000      10    0    0    0     0       0          0\n\
+  eth0:     300       3    0    0    0     0          0         0      400       4    0    0    0     0       0          0\n\
+ wlan0:      20       2    0    0    0     0          0         0       10       1    0    0    0     0       0          0\n")
+        ]);
+        assert_eq!(network(root.to_str().unwrap()), Some((320, 410)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn volumeReadsTheFirstChannel() {
+        let root = fixture("volume", &[
+            ("on", "Simple mixer control 'Master',0\n  Capabilities: pvolume pswitch\n  Limits: Playback 0 - 87\n\
+  Front Left: Playback 35 [40%] [-39.00dB] [on]\n  Front Right: Playback 70 [80%] [-12.00dB] [on]\n"),
+            ("off", "Simple mixer control 'Master',0\n  Mono: Playback 87 [100%] [0.00dB] [off]\n")
+        ]);
+        assert_eq!(volume(&format!("cat {}", root.join("on").display())), Some((40, false)));
+        assert_eq!(volume(&format!("cat {}", root.join("off").display())), Some((100, true)));
+        assert_eq!(volume(&format!("cat {}", root.join("missing").display())), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn missingFiles() {
+        let root = fixture("empty", &[]);
+        let root = root.to_str().unwrap();
+        assert_eq!(cpu(root), None);
+        assert_eq!(memory(root), None);
+        assert_eq!(load(root), None);
+        assert_eq!(network(root), None);
+    }
+
+    #[test]
+    fn humanSizes() {
+        assert_eq!(humanSize(0), "0B");
+        assert_eq!(humanSize(1023), "1023B");
+        assert_eq!(humanSize(1536), "1.5K");
+        assert_eq!(humanSize(10 * 1024), "10K");
+        assert_eq!(humanSize(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+
+    #[test]
+    fn fillReplacesValues() {
+        assert_eq!(fill("CPU {usage}% {usage}", &[("usage", String::from("12"))]), "CPU 12% 12");
+        assert_eq!(fill("{unknown} {a}", &[("a", String::from("1"))]), "{unknown} 1");
+    }
+}
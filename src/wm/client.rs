@@ -2,25 +2,28 @@ extern crate x11;
 
 use x11::xlib;
 
+use wm;
 use wm::workspace::Workspace;
 use config;
+use Click;
+use { WMSTATE, WITHDRAWNSTATE, NORMALSTATE, ICONICSTATE, NETWMNAME, NETWMDESKTOP };
 
 /**
  * Stores a Client (wrapper around the xlib::Window struct)
  */
-pub struct Client<'a> {
-    pub name: &'a str,
+pub struct Client {
+    pub name: String,
     pub mina: f32, pub maxa: f32,
     pub x: i32, pub y: i32, pub w: u32, pub h: u32,
-    pub bw: u32,
+    pub bw: u32, pub oldbw: u32,
     pub wsindex: usize,
     pub tags: u32,
     pub isfixed: bool, pub isfloating: bool, pub isurgent: bool, pub neverfocus: bool, pub oldwm:bool, pub isfullscreen: bool, pub oldstate: bool,
     pub win: xlib::Window
 }
 
-impl<'a> PartialEq for Client<'a> {
-    fn eq(&self, other: &Client<'a>) -> bool {
+impl PartialEq for Client {
+    fn eq(&self, other: &Client) -> bool {
         self.win == other.win
     }
 }
@@ -28,12 +31,12 @@ impl<'a> PartialEq for Client<'a> {
 /**
  * Create a new client from a window ant it's attributes
  */
-pub fn createClient<'a>(win: xlib::Window, wa: xlib::XWindowAttributes, wsindex: usize) -> Client<'a> {
+pub fn createClient(win: xlib::Window, wa: xlib::XWindowAttributes, wsindex: usize) -> Client {
     Client {
-        name: "",
+        name: String::new(),
         mina: 0.0, maxa: 0.0,
         x: wa.x, y: wa.y, w: wa.width as u32, h: wa.height as u32,
-        bw: config::borderpx,
+        bw: config::borderpx, oldbw: wa.border_width as u32,
         wsindex,
         tags: 0,
         isfixed: false, isfloating: false, isurgent: false, neverfocus: false, oldwm: false, isfullscreen: false, oldstate: false,
@@ -44,7 +47,7 @@ pub fn createClient<'a>(win: xlib::Window, wa: xlib::XWindowAttributes, wsindex:
 /**
  * Finds the Client containing a Window
  */
-pub fn findFromWindow<'a>(window : xlib::Window, mons: &'a Vec<Workspace<'a>>) -> Option<&'a Client<'a>> {
+pub fn findFromWindow<'a>(window : xlib::Window, mons: &'a Vec<Workspace<'a>>) -> Option<&'a Client> {
     for m in mons.iter() {
         for c in m.clients.iter() {
             if c.win == window {
@@ -85,7 +88,7 @@ pub fn setGeom(c: Client, x:i32, y:i32, w: u32, h: u32) -> Client {
 /**
  * Change configuration of the window : sends a Configure event
  */
-pub fn configure<'a>(c: &'a Client<'a>, dpy: &mut xlib::Display) {
+pub fn configure(c: &Client, dpy: &mut xlib::Display) {
     let mut wc = xlib::XWindowChanges {
         x: c.x, y: c.y,
         width: c.w as i32, height: c.h as i32,
@@ -99,15 +102,76 @@ pub fn configure<'a>(c: &'a Client<'a>, dpy: &mut xlib::Display) {
 /**
  * Draws the Window on the screen
  */
-pub fn show(c: &Client, dpy: &mut xlib::Display) {
-    unsafe { xlib::XMapWindow(dpy, c.win) };
+pub fn show(c: &Client, dpy: &mut xlib::Display, wmatom: &[xlib::Atom]) {
+    unsafe {
+        xlib::XMoveWindow(dpy, c.win, c.x, c.y);
+        xlib::XMapWindow(dpy, c.win);
+    }
+    setState(c, dpy, wmatom, NORMALSTATE);
+}
+
+/**
+ * Hides the Window from the screen, by moving it out of the screen. The window stays mapped, so
+ * every UnmapNotify we get for a Client comes from the client itself.
+ */
+pub fn hide(c: &Client, dpy: &mut xlib::Display, wmatom: &[xlib::Atom]) {
+    unsafe { xlib::XMoveWindow(dpy, c.win, -2 * width(c) as i32, c.y) };
+    setState(c, dpy, wmatom, ICONICSTATE);
+}
+
+/**
+ * Grabs the buttons of the Window : every button when it is not focused (click to focus), only
+ * the ClkClientWin buttons of config::buttons when it is focused
+ */
+pub fn grabButtons(c: &Client, dpy: &mut xlib::Display, numlockmask: u32, focused: bool) {
+    let modifiers = [0, xlib::LockMask, numlockmask, numlockmask|xlib::LockMask];
+    let buttonmask = (xlib::ButtonPressMask|xlib::ButtonReleaseMask) as u32;
+    unsafe { xlib::XUngrabButton(dpy, xlib::AnyButton as u32, xlib::AnyModifier, c.win) };
+    if focused {
+        for b in config::buttons.iter().filter(|b| { b.click == Click::ClkClientWin }) {
+            for m in modifiers.iter() {
+                unsafe { xlib::XGrabButton(dpy, b.button, b.mask|m, c.win, 0, buttonmask, xlib::GrabModeAsync, xlib::GrabModeSync, 0, 0) };
+            }
+        }
+    } else {
+        unsafe { xlib::XGrabButton(dpy, xlib::AnyButton as u32, xlib::AnyModifier, c.win, 0, buttonmask, xlib::GrabModeSync, xlib::GrabModeSync, 0, 0) };
+    }
+}
+
+/**
+ * Sets the ICCCM WM_STATE property of the Window
+ */
+pub fn setState(c: &Client, dpy: &mut xlib::Display, wmatom: &[xlib::Atom], state: i64) {
+    let data: [i64; 2] = [state, 0]; // No icon window
+    unsafe { xlib::XChangeProperty(dpy, c.win, wmatom[WMSTATE], wmatom[WMSTATE], 32, xlib::PropModeReplace, data.as_ptr() as *const u8, 2) };
+}
+
+/**
+ * Sets the EWMH _NET_WM_DESKTOP property of the Window to the index of its Workspace
+ */
+pub fn setDesktop(c: &Client, dpy: &mut xlib::Display, netatom: &[xlib::Atom]) {
+    let data = c.wsindex as i64;
+    unsafe { xlib::XChangeProperty(dpy, c.win, netatom[NETWMDESKTOP], xlib::XA_CARDINAL, 32, xlib::PropModeReplace, &data as *const i64 as *const u8, 1) };
 }
 
 /**
- * Hides the Window from the screen
+ * Gives the Window back to X : puts it back on the screen with its original border
  */
-pub fn hide(c: &Client, dpy: &mut xlib::Display) {
-    unsafe { xlib::XUnmapWindow(dpy, c.win) };
+pub fn release(c: &Client, dpy: &mut xlib::Display, wmatom: &[xlib::Atom]) {
+    let mut wc = xlib::XWindowChanges {
+        x: c.x, y: c.y,
+        width: c.w as i32, height: c.h as i32,
+        border_width: c.oldbw as i32,
+        sibling: 0,
+        stack_mode: 0
+    };
+    unsafe {
+        xlib::XConfigureWindow(dpy, c.win, (xlib::CWX|xlib::CWY|xlib::CWBorderWidth) as u32, &mut wc);
+        xlib::XUngrabButton(dpy, xlib::AnyButton as u32, xlib::AnyModifier, c.win);
+        xlib::XSelectInput(dpy, c.win, xlib::NoEventMask);
+        xlib::XMapWindow(dpy, c.win);
+    }
+    setState(c, dpy, wmatom, WITHDRAWNSTATE);
 }
 
 /**
@@ -161,10 +225,14 @@ pub fn destroyClient(c: Client, dpy: &mut xlib::Display) {
 // }
 
 /**
- * Updates the title
+ * Updates the title, from _NET_WM_NAME or WM_NAME
  */
-pub fn updateTitle(c: Client) -> Client {
-    Client { ..c }
+pub fn updateTitle(c: Client, dpy: &mut xlib::Display, netatom: &[xlib::Atom]) -> Client {
+    let name = match wm::getTextProp(dpy, c.win, netatom[NETWMNAME]) {
+        Some(name) => name,
+        None => wm::getTextProp(dpy, c.win, xlib::XA_WM_NAME).unwrap_or(String::from("broken"))
+    };
+    Client { name, ..c }
 }
 
 /*
@@ -257,24 +325,6 @@ pub fn updateTitle(c: Client) -> Client {
 //     }
 // }
 
-/*
- * Grabs buttons
- */
-// pub fn grabbuttons(&mut self, wm: &mut WM, focused: bool) {
-//     wm.updatenumlockmask();
-//     let modifiers = vec![0, xlib::LockMask, wm.numlockmask, xlib::LockMask|wm.numlockmask];
-//     unsafe { xlib::XUngrabButton(wm.drw.dpy, xlib::AnyButton as u32, xlib::AnyModifier, self.win) };
-//     if focused {
-//         for b in config::buttons.iter() {
-//             /*if b.click == ClkClientWin {
-//             TODO
-//         }*/
-//         }
-//     } else {
-//         unsafe { xlib::XGrabButton(wm.drw.dpy, xlib::AnyButton as u32, xlib::AnyModifier, self.win, 0, (xlib::ButtonPressMask|xlib::ButtonReleaseMask) as u32, xlib::GrabModeAsync, xlib::GrabModeSync, 0, 0) };
-//     }
-// }
-
 /*
  * Applies the rules
  */
@@ -1,6 +1,10 @@
 extern crate x11;
 
-use std::ffi::CString;
+use std::ptr;
+use std::mem;
+use std::slice;
+use std::ffi::{ CString, CStr };
+use std::os::raw::{ c_char, c_long, c_void };
 
 use x11::xlib;
 use x11::keysym;
@@ -9,14 +13,22 @@ use x11::keysym;
 pub mod workspace;
 /// Client module
 pub mod client;
+/// Monitor module
+pub mod monitor;
 
-use CURNORMAL;
+use { CURNORMAL, SCHEMENORM, SCHEMESEL, WITHDRAWNSTATE, ICONICSTATE, WMSTATE, NETWMDESKTOP, NETACTIVEWINDOW };
 use wm::workspace::Workspace;
+use wm::monitor::Monitor;
 use drw;
 use drw::{ Drw, Cur };
 use drw::clrscheme;
 use drw::clrscheme::ClrScheme;
 use config;
+use eventloop::{ Timer, FdWatch };
+use status;
+use status::modules::ModuleState;
+use status::markup;
+use status::markup::Item;
 
 /**
  * Stores the state of the Window Manager
@@ -32,10 +44,19 @@ pub struct WM<'a> {
     pub scheme: Vec<ClrScheme>,
     pub wss: Vec<Workspace<'a>>,
     pub selwsindex: usize,
+    pub mons: Vec<Monitor>,
+    pub selmonindex: usize,
     pub sw: u32, pub sh: u32,
     pub bh: u32,
     pub stext: String,
+    pub stextitems: Vec<Item>,
     pub numlockmask: u32,
+    pub wmcheckwin: xlib::Window,
+    pub restart: bool,
+    pub timers: Vec<Timer>,
+    pub watches: Vec<FdWatch>,
+    pub statusmodules: Vec<ModuleState>,
+    pub redraw: bool,  // The bars have to be drawn again
 }
 
 /**
@@ -53,10 +74,19 @@ pub fn initWm(drw: Drw, screen: i32, root: u64, sw: u32, sh: u32) -> WM {
         scheme: Vec::new(),
         wss: Vec::new(),
         selwsindex: 0,
+        mons: Vec::new(),
+        selmonindex: 0,
         sw, sh,
         bh: 0,
         stext: String::from("dwm-rust"),
-        numlockmask: 0
+        stextitems: markup::parse("dwm-rust"),
+        numlockmask: 0,
+        wmcheckwin: 0,
+        restart: false,
+        timers: Vec::new(),
+        watches: Vec::new(),
+        statusmodules: Vec::new(),
+        redraw: true
     };
     wm.bh = wm.drw.fonts[0].h + 2;
     unsafe {
@@ -73,6 +103,8 @@ pub fn initWm(drw: Drw, screen: i32, root: u64, sw: u32, sh: u32) -> WM {
         wm.netatom.push(xlib::XInternAtom(wm.drw.dpy, CString::new("_NET_WM_WINDOWN_TYPE").unwrap().as_ptr(), 0));
         wm.netatom.push(xlib::XInternAtom(wm.drw.dpy, CString::new("_NET_WM_WINDOW_TYPE_DIALOG").unwrap().as_ptr(), 0));
         wm.netatom.push(xlib::XInternAtom(wm.drw.dpy, CString::new("_NET_CLIENT_LIST").unwrap().as_ptr(), 0));
+        wm.netatom.push(xlib::XInternAtom(wm.drw.dpy, CString::new("_NET_SUPPORTING_WM_CHECK").unwrap().as_ptr(), 0));
+        wm.netatom.push(xlib::XInternAtom(wm.drw.dpy, CString::new("_NET_WM_DESKTOP").unwrap().as_ptr(), 0));
         // Init cursors
         wm.cursor.push(drw::createCur(&mut (wm.drw), 68)); // Normal
         wm.cursor.push(drw::createCur(&mut (wm.drw), 120)); // Resize
@@ -100,38 +132,31 @@ pub fn setRootBackground(wm: WM) -> WM {
 }
 
 /**
- * Create all the workspaces and set their data
+ * Creates the monitors (one per Xinerama screen)
+ */
+pub fn createMonitors(wm: WM) -> WM {
+    let mons = monitor::createMonitors(wm.drw.dpy, wm.sw, wm.sh);
+    WM { mons, selmonindex: 0, ..wm }
+}
+
+/**
+ * Create all the workspaces and set their data : the first ones are shown on the monitors, the
+ * others are hidden on the first monitor
  */
 pub fn createWorkspaces(wm: WM) -> WM {
-    WM {
-        wss:config::tags.iter().map(|t| {
-            let ws = workspace::createWorkspace(t);
-            workspace::updateBarPos(workspace::Workspace {
-                w: wm.sw, h: wm.sh,
-                ..ws
-            }, wm.bh)
+    let mut wm = WM {
+        wss: config::tags.iter().enumerate().map(|(i, t)| {
+            let m = if i < wm.mons.len() { &wm.mons[i] } else { &wm.mons[0] };
+            workspace::moveTo(workspace::createWorkspace(t), m.x, m.y, m.w, m.h, wm.bh)
         }).collect(),
         selwsindex: 0,
         ..wm
-    }
+    };
+    // Each monitor shows its own workspace
+    let n = wm.wss.len();
+    wm.mons.truncate(n);
+    wm
 }
-// /**
-//  * Grabs buttons
-//  */
-// pub fn grabbuttons(&mut wm, c: &Client, focused: bool) {
-//     wm.updatenumlockmask();
-//     let modifiers = vec![0, xlib::LockMask, wm.numlockmask, xlib::LockMask|wm.numlockmask];
-//     unsafe { xlib::XUngrabButton(wm.drw.dpy, xlib::AnyButton as u32, xlib::AnyModifier, c.win) };
-//     if focused {
-//         for b in config::buttons.iter() {
-//             /*if b.click == ClkClientWin {
-//             TODO
-//         }*/
-//         }
-//     } else {
-//         unsafe { xlib::XGrabButton(wm.drw.dpy, xlib::AnyButton as u32, xlib::AnyModifier, c.win, 0, (xlib::ButtonPressMask|xlib::ButtonReleaseMask) as u32, xlib::GrabModeAsync, xlib::GrabModeSync, 0, 0) };
-//     }
-// }
 
 fn updatenumlockmask(wm: WM) -> WM {
     let modmap = unsafe { (*xlib::XGetModifierMapping(wm.drw.dpy)) };
@@ -190,11 +215,11 @@ pub fn updateBars(mut wm: WM) -> WM {
         colormap: xlib::CopyFromParent as u64,
         cursor: 0
     };
-    if wm.wss[0].barwin == 0 {
-        let barwin = unsafe {
+    for m in wm.mons.iter_mut().filter(|m| { m.barwin == 0 }) {
+        m.barwin = unsafe {
             xlib::XCreateWindow(wm.drw.dpy,
                                 wm.root,
-                                wm.wss[0].x, wm.wss[0].by, wm.wss[0].w as u32,
+                                m.x, m.y, m.w,
                                 wm.bh,
                                 0,
                                 xlib::XDefaultDepth(wm.drw.dpy, wm.screen),
@@ -202,40 +227,300 @@ pub fn updateBars(mut wm: WM) -> WM {
                                 xlib::XDefaultVisual(wm.drw.dpy, wm.screen),
                                 xlib::CWOverrideRedirect|xlib::CWBackPixmap|xlib::CWEventMask,
                                 &mut wa) };
-        unsafe { xlib::XDefineCursor(wm.drw.dpy, barwin, wm.cursor[CURNORMAL].cursor) };
-        unsafe { xlib::XMapRaised(wm.drw.dpy, barwin) };
-        for ws in wm.wss.iter_mut() {
-            if ws.barwin == 0 { ws.barwin = barwin };
-        }
+        unsafe { xlib::XDefineCursor(wm.drw.dpy, m.barwin, wm.cursor[CURNORMAL].cursor) };
+        unsafe { xlib::XMapRaised(wm.drw.dpy, m.barwin) };
+    }
+    updateBarWin(wm)
+}
+
+/**
+ * Moves the bar window of each monitor to the bar position of the Workspace it shows (off-screen
+ * when its bar is hidden)
+ */
+pub fn updateBarWin(wm: WM) -> WM {
+    for m in wm.mons.iter() {
+        let ws = &wm.wss[m.wsindex];
+        unsafe { xlib::XMoveResizeWindow(wm.drw.dpy, m.barwin, ws.x, ws.by, ws.w, wm.bh) };
     }
+    invalidateBars(wm)
+}
+
+/**
+ * Selects a monitor, and the Workspace it shows
+ */
+pub fn selectMon(wm: WM, monindex: usize) -> WM {
+    let selwsindex = wm.mons[monindex].wsindex;
+    WM { selmonindex: monindex, selwsindex, ..wm }
+}
+
+/**
+ * Shows the Workspace wsindex on the selected monitor and selects it. If it was shown on another
+ * monitor, the two monitors swap their Workspaces, otherwise the previous one is hidden.
+ */
+pub fn view(mut wm: WM, wsindex: usize) -> WM {
+    let old = wm.selwsindex;
+    if wsindex == old {
+        return wm;
+    }
+    match monitor::showing(&wm.mons, wsindex) {
+        Some(m) => wm = showOnMon(wm, old, m),
+        None => workspace::hideAllClients(&wm.wss[old], wm.drw.dpy, &wm.wmatom)
+    }
+    let selmonindex = wm.selmonindex;
+    let wm = showOnMon(wm, wsindex, selmonindex);
+    updateBarWin(WM { selwsindex: wsindex, ..wm })
+}
+
+/**
+ * Shows the Workspace wsindex on a monitor : moves it to the geometry of the monitor and draws its
+ * Clients
+ */
+fn showOnMon(mut wm: WM, wsindex: usize, monindex: usize) -> WM {
+    let ws = {
+        let m = &wm.mons[monindex];
+        workspace::moveTo(wm.wss.remove(wsindex), m.x, m.y, m.w, m.h, wm.bh)
+    };
+    let ws = workspace::updateGeom(ws, wm.drw.dpy);
+    workspace::showAllClients(&ws, wm.drw.dpy, &wm.wmatom);
+    wm.wss.insert(wsindex, ws);
+    wm.mons[monindex].wsindex = wsindex;
     wm
 }
 
-fn getTextProp(dpy: &mut xlib::Display, w: xlib::Window, atom: xlib::Atom) -> Option<String> {
-    let mut name = xlib::XTextProperty { // Dummy value
-        value: (CString::new("").unwrap().into_bytes().as_mut_ptr()), encoding: 0, format: 8, nitems: 0
+/**
+ * Reads a text property of a Window, converted to UTF-8
+ */
+pub fn getTextProp(dpy: &mut xlib::Display, w: xlib::Window, atom: xlib::Atom) -> Option<String> {
+    let mut name = xlib::XTextProperty {
+        value: ptr::null_mut(), encoding: 0, format: 0, nitems: 0
+    };
+    if unsafe { xlib::XGetTextProperty(dpy, w, &mut name, atom) } == 0 || name.value.is_null() {
+        return None;
+    }
+    let mut list: *mut *mut c_char = ptr::null_mut();
+    let mut n = 0;
+    let text = if name.nitems == 0 {
+        String::new()
+    } else if unsafe { xlib::Xutf8TextPropertyToTextList(dpy, &name, &mut list, &mut n) } >= xlib::Success as i32 && n > 0 && !list.is_null() {
+        let text = unsafe { CStr::from_ptr(*list) }.to_string_lossy().into_owned();
+        unsafe { xlib::XFreeStringList(list) };
+        text
+    } else {
+        String::from_utf8_lossy(unsafe { slice::from_raw_parts(name.value, name.nitems as usize) }).into_owned()
     };
-    unsafe { xlib::XGetTextProperty(dpy, w, &mut name, atom) };
-    let text = unsafe { CString::from_vec_unchecked(Vec::from_raw_parts(name.value, name.nitems as usize, name.nitems as usize))}.into_string().unwrap();
+    unsafe { xlib::XFree(name.value as *mut c_void) };
     if text == "" { None } else { Some(text) }
 }
 
 /**
- * Updates the status bar text
+ * Reads the first item of a 32 bits property of a Window
+ */
+pub fn getLongProp(dpy: &mut xlib::Display, w: xlib::Window, prop: xlib::Atom, reqtype: xlib::Atom) -> Option<i64> {
+    let mut realtype = 0; let mut format = 0;
+    let mut n = 0; let mut extra = 0;
+    let mut p: *mut u8 = ptr::null_mut();
+    if unsafe { xlib::XGetWindowProperty(dpy, w, prop, 0, 2, 0, reqtype, &mut realtype, &mut format, &mut n, &mut extra, &mut p) } != xlib::Success as i32 || p.is_null() {
+        return None;
+    }
+    let value = if n > 0 && format == 32 { Some(unsafe { *(p as *const c_long) } as i64) } else { None };
+    unsafe { xlib::XFree(p as *mut c_void) };
+    value
+}
+
+/**
+ * Updates the status bar text and the bars, once the pending events are handled (see refreshBars)
  */
 pub fn updateStatus(wm: WM) -> WM {
-    let wm = WM {
-        stext: if let Some(text) = getTextProp(wm.drw.dpy, wm.root, xlib::XA_WM_NAME) { text } else { wm.stext },
-        ..wm
+    WM { redraw: true, ..wm }
+}
+
+/**
+ * Updates the status bar text, and draws the bars
+ */
+pub fn refreshBars(wm: WM) -> WM {
+    let stext = if let Some(text) = getTextProp(wm.drw.dpy, wm.root, xlib::XA_WM_NAME) { text } else { status::defaultText(&wm.statusmodules) };
+    // The markup is only parsed when the text changes
+    let wm = if stext != wm.stext {
+        WM {
+            stextitems: markup::parse(&stext),
+            stext,
+            ..wm
+        }
+    } else { wm };
+    drawBars(WM { redraw: false, ..wm })
+}
+
+/**
+ * Draws the bar of every monitor, with the status text on the primary monitor (or on every monitor,
+ * see config::statusAllMons)
+ */
+pub fn drawBars(wm: WM) -> WM {
+    let WM { mut drw, mut mons, .. } = wm;
+    for (i, mon) in mons.iter_mut().enumerate() {
+        let status = if i == 0 || config::statusAllMons { Some(&wm.stextitems[..]) } else { None };
+        let (d, bar) = workspace::drawBar(drw, wm.bh, &wm.scheme, &wm.wss, mon, i == wm.selmonindex, status);
+        drw = d;
+        mon.bar = bar;
+    }
+    WM { drw, mons, ..wm }
+}
+
+/**
+ * Forgets what is drawn on the bars, so that they are entirely drawn again
+ */
+pub fn invalidateBars(mut wm: WM) -> WM {
+    for m in wm.mons.iter_mut() {
+        m.bar = workspace::createBarCache();
+    }
+    wm
+}
+
+/**
+ * State of a Client saved across a restart
+ */
+struct SavedClient {
+    win: xlib::Window,
+    wsindex: usize,
+    isfloating: bool,
+    x: i32, y: i32, w: u32, h: u32
+}
+
+/// Root window property storing the state of the WM across a restart
+const STATEPROP: &str = "_DWM_RUST_STATE";
+
+/**
+ * Saves the selected Workspace and the Workspace, floating state and geometry of every Client in a
+ * property of the root window, so that they can be restored after a restart
+ */
+pub fn saveState(wm: WM) -> WM {
+    let mut state = format!("{}\n", wm.selwsindex);
+    for (i, ws) in wm.wss.iter().enumerate() {
+        for c in ws.clients.iter() {
+            state.push_str(&format!("{} {} {} {} {} {} {}\n", c.win, i, c.isfloating as u8, c.x, c.y, c.w, c.h));
+        }
+    }
+    unsafe {
+        let atom = xlib::XInternAtom(wm.drw.dpy, CString::new(STATEPROP).unwrap().as_ptr(), 0);
+        xlib::XChangeProperty(wm.drw.dpy, wm.root, atom, xlib::XA_STRING, 8, xlib::PropModeReplace, state.as_ptr(), state.len() as i32);
+    }
+    wm
+}
+
+/**
+ * Reads (and deletes) the state saved by saveState, if any
+ */
+fn loadState(wm: WM) -> (WM, Option<usize>, Vec<SavedClient>) {
+    let atom = unsafe { xlib::XInternAtom(wm.drw.dpy, CString::new(STATEPROP).unwrap().as_ptr(), 0) };
+    let state = match getTextProp(wm.drw.dpy, wm.root, atom) {
+        Some(state) => state,
+        None => return (wm, None, Vec::new())
     };
-    WM {drw: workspace::drawBar(wm.drw, wm.bh, &wm.scheme, &wm.wss, wm.selwsindex, &wm.stext[..]), ..wm}
+    unsafe { xlib::XDeleteProperty(wm.drw.dpy, wm.root, atom) };
+    let mut lines = state.lines();
+    let selwsindex = lines.next().and_then(|l| { l.trim().parse().ok() });
+    let saved = lines.filter_map(|l| {
+        let v: Vec<&str> = l.split_whitespace().collect();
+        if v.len() != 7 { return None }
+        Some(SavedClient {
+            win: v[0].parse().ok()?,
+            wsindex: v[1].parse().ok()?,
+            isfloating: v[2] == "1",
+            x: v[3].parse().ok()?, y: v[4].parse().ok()?,
+            w: v[5].parse().ok()?, h: v[6].parse().ok()?
+        })
+    }).collect();
+    (wm, selwsindex, saved)
 }
 
 /**
- * Manage a new Window
+ * Gives back to a managed Client the state it had before a restart
  */
-pub fn manage<'a>(mut wm: WM<'a>, w: xlib::Window, wa: xlib::XWindowAttributes) -> WM<'a> {
-    let c = client::updateTitle(client::createClient(w, wa, wm.selwsindex));
+fn restoreClient<'a>(mut wm: WM<'a>, saved: &SavedClient) -> WM<'a> {
+    let wsindex = saved.wsindex;
+    if let Some(i) = wm.wss[wsindex].clients.iter().position(|c| { c.win == saved.win }) {
+        let c = wm.wss[wsindex].clients.remove(i);
+        wm.wss[wsindex].clients.insert(i, client::Client {
+            isfloating: saved.isfloating,
+            x: saved.x, y: saved.y, w: saved.w, h: saved.h,
+            ..c
+        });
+        // The windows of hidden workspaces stay out of the screen
+        if monitor::showing(&wm.mons, wsindex).is_some() {
+            let ws = workspace::updateGeom(wm.wss.remove(wsindex), wm.drw.dpy);
+            wm.wss.insert(wsindex, ws);
+            if let Some(c) = wm.wss[wsindex].clients.get(i) {
+                client::show(c, wm.drw.dpy, &wm.wmatom);
+            }
+        } else {
+            let ws = workspace::arrange(wm.wss.remove(wsindex));
+            workspace::hideAllClients(&ws, wm.drw.dpy, &wm.wmatom);
+            wm.wss.insert(wsindex, ws);
+        }
+    }
+    wm
+}
+
+/**
+ * Manages the windows which already exist when the WM starts (restoring their state after a restart)
+ */
+pub fn scan(wm: WM) -> WM {
+    let (wm, selwsindex, saved) = loadState(wm);
+    let wm = match selwsindex {
+        Some(i) if i < wm.wss.len() => view(wm, i),
+        _ => wm
+    };
+    let mut d1 = 0; let mut d2 = 0;
+    let mut wins: *mut xlib::Window = ptr::null_mut();
+    let mut num = 0;
+    if unsafe { xlib::XQueryTree(wm.drw.dpy, wm.root, &mut d1, &mut d2, &mut wins, &mut num) } == 0 || wins.is_null() {
+        return wm;
+    }
+    let children = unsafe { slice::from_raw_parts(wins, num as usize) }.to_vec();
+    unsafe { xlib::XFree(wins as *mut c_void) };
+    // Normal windows first, so that transients can find their parent
+    let wm = children.iter().fold(wm, |wm, &w| { scanWindow(wm, w, false, &saved) });
+    children.iter().fold(wm, |wm, &w| { scanWindow(wm, w, true, &saved) })
+}
+
+/**
+ * Manages an existing Window if it is visible (or iconified) and is a transient or not
+ */
+fn scanWindow<'a>(wm: WM<'a>, w: xlib::Window, transient: bool, saved: &[SavedClient]) -> WM<'a> {
+    let mut wa: xlib::XWindowAttributes = unsafe { mem::zeroed() };
+    let mut trans = 0;
+    if unsafe { xlib::XGetWindowAttributes(wm.drw.dpy, w, &mut wa) } == 0 || wa.override_redirect != 0
+    || (unsafe { xlib::XGetTransientForHint(wm.drw.dpy, w, &mut trans) } != 0) != transient {
+        return wm;
+    }
+    let wmstate = wm.wmatom[WMSTATE];
+    if wa.map_state == xlib::IsViewable || getLongProp(wm.drw.dpy, w, wmstate, wmstate) == Some(ICONICSTATE) {
+        let desktop = getLongProp(wm.drw.dpy, w, wm.netatom[NETWMDESKTOP], xlib::XA_CARDINAL);
+        let wsindex = match desktop {
+            Some(d) if d >= 0 && (d as usize) < wm.wss.len() => d as usize,
+            _ => wm.selwsindex
+        };
+        match saved.iter().find(|s| { s.win == w && s.wsindex < wm.wss.len() }) {
+            Some(s) => restoreClient(manage(wm, w, wa, s.wsindex), s),
+            None => manage(wm, w, wa, wsindex)
+        }
+    } else {
+        wm
+    }
+}
+
+/**
+ * Manage a new Window, on the Workspace wsindex (or the one of the window it is transient for)
+ */
+pub fn manage<'a>(mut wm: WM<'a>, w: xlib::Window, wa: xlib::XWindowAttributes, wsindex: usize) -> WM<'a> {
+    let mut trans = 0;
+    let wsindex = if unsafe { xlib::XGetTransientForHint(wm.drw.dpy, w, &mut trans) } != 0 {
+        client::findFromWindow(trans, &wm.wss).map(|t| { t.wsindex }).unwrap_or(wsindex)
+    } else {
+        wsindex
+    };
+    let c = client::updateTitle(client::createClient(w, wa, wsindex), wm.drw.dpy, &wm.netatom);
+    unsafe { xlib::XSelectInput(wm.drw.dpy, w, xlib::PropertyChangeMask) };
+    client::setDesktop(&c, wm.drw.dpy, &wm.netatom);
     // let mut trans = 0;
     // if unsafe { xlib::XGetTransientForHint(wm.drw.dpy, w, &mut trans) } != 0 {
     //     if let Some(t) = Client::from(trans, &wm.mons) {
@@ -266,7 +551,6 @@ pub fn manage<'a>(mut wm: WM<'a>, w: xlib::Window, wa: xlib::XWindowAttributes)
     // c.updatesizehints(wm.drw.dpy);
     // c.updatewmhints(wm.drw.dpy, &wm.mons[wm.selmonindex]);
     // unsafe { xlib::XSelectInput(wm.drw.dpy, w, xlib::EnterWindowMask | xlib::FocusChangeMask | xlib::PropertyChangeMask | xlib::StructureNotifyMask) };
-    // // wm.grabbuttons(&c, false); TODO
     // if !c.isfloating {
     //     c.isfloating = trans != 0 || c.isfixed;
     //     c.oldstate = c.isfloating;
@@ -276,35 +560,107 @@ pub fn manage<'a>(mut wm: WM<'a>, w: xlib::Window, wa: xlib::XWindowAttributes)
     // }
     // TODO
 
-    // Add the client to the current workspace
-    wm.wss[wm.selwsindex].clients.insert(0, c);
-    // Update geometry of the current workspace
-    let ws = workspace::updateGeom(wm.wss.remove(wm.selwsindex), wm.drw.dpy);
-    wm.wss.insert(wm.selwsindex, ws);
-    // Draw the client on the screen
-    if let Some(c) = wm.wss[wm.selwsindex].clients.first() {
-        client::show(c, wm.drw.dpy);
+    // Add the client to its workspace, and select it
+    wm.wss[wsindex].clients.insert(0, c);
+    wm.wss[wsindex].sel = Some(w);
+    // Update geometry of the workspace, and draw the client on the screen, or keep the workspace
+    // out of the screen if it is hidden
+    let visible = monitor::showing(&wm.mons, wsindex).is_some();
+    let ws = if visible {
+        workspace::updateGeom(wm.wss.remove(wsindex), wm.drw.dpy)
+    } else {
+        workspace::arrange(wm.wss.remove(wsindex))
+    };
+    wm.wss.insert(wsindex, ws);
+    if let Some(c) = wm.wss[wsindex].clients.first() {
+        client::grabButtons(c, wm.drw.dpy, wm.numlockmask, false);
+        if visible {
+            client::show(c, wm.drw.dpy, &wm.wmatom);
+        } else {
+            workspace::hideAllClients(&wm.wss[wsindex], wm.drw.dpy, &wm.wmatom);
+            unsafe { xlib::XMapWindow(wm.drw.dpy, c.win) };
+        }
+    }
+    if wsindex == wm.selwsindex { focus(wm, Some(w)) } else { wm }
+}
+
+/**
+ * Focuses a Client of the current Workspace (None keeps the selected Client, or selects another one
+ * if it is gone) : sets the input focus and borders, and grabs the buttons of the Clients of every
+ * visible Workspace
+ */
+pub fn focus<'a>(mut wm: WM<'a>, w: Option<xlib::Window>) -> WM<'a> {
+    let selwsindex = wm.selwsindex;
+    if w.is_some() {
+        wm.wss[selwsindex].sel = w;
+    }
+    let ws = workspace::updateSel(wm.wss.remove(selwsindex));
+    wm.wss.insert(selwsindex, ws);
+    let sel = wm.wss[selwsindex].sel;
+    for m in wm.mons.iter() {
+        for c in wm.wss[m.wsindex].clients.iter() {
+            let focused = m.wsindex == selwsindex && Some(c.win) == sel;
+            let border = if focused { wm.scheme[SCHEMESEL].border.pix } else { wm.scheme[SCHEMENORM].border.pix };
+            unsafe { xlib::XSetWindowBorder(wm.drw.dpy, c.win, border) };
+            client::grabButtons(c, wm.drw.dpy, wm.numlockmask, focused);
+        }
+    }
+    unsafe {
+        match sel {
+            Some(w) => {
+                xlib::XSetInputFocus(wm.drw.dpy, w, xlib::RevertToPointerRoot, xlib::CurrentTime);
+                xlib::XChangeProperty(wm.drw.dpy, wm.root, wm.netatom[NETACTIVEWINDOW], xlib::XA_WINDOW, 32, xlib::PropModeReplace, &w as *const u64 as *const u8, 1);
+            },
+            None => {
+                xlib::XSetInputFocus(wm.drw.dpy, wm.root, xlib::RevertToPointerRoot, xlib::CurrentTime);
+                xlib::XDeleteProperty(wm.drw.dpy, wm.root, wm.netatom[NETACTIVEWINDOW]);
+            }
+        }
+    }
+    wm
+}
+
+/**
+ * Updates the title of the Client containing a Window
+ */
+pub fn updateClientTitle<'a>(mut wm: WM<'a>, w: xlib::Window) -> WM<'a> {
+    for ws in wm.wss.iter_mut() {
+        for i in 0..ws.clients.len() {
+            if ws.clients[i].win == w {
+                let c = client::updateTitle(ws.clients.remove(i), wm.drw.dpy, &wm.netatom);
+                ws.clients.insert(i, c);
+                return wm;
+            }
+        }
     }
     wm
-    // focus(None) TODO
 }
 
 /**
- * Unmanage a Client
+ * Unmanage a Client (`destroyed` tells if the Window still exists)
  */
-pub fn unManage<'a>(wm: WM<'a>, w: xlib::Window) -> WM<'a> {
+pub fn unManage<'a>(wm: WM<'a>, w: xlib::Window, destroyed: bool) -> WM<'a> {
+    if !destroyed {
+        if let Some(c) = client::findFromWindow(w, &wm.wss) {
+            client::setState(c, wm.drw.dpy, &wm.wmatom, WITHDRAWNSTATE);
+        }
+    }
     let mut wm = WM {
         wss : wm.wss.into_iter().map(|ws| {
-            Workspace {
+            workspace::updateSel(Workspace {
                 clients: ws.clients.into_iter().filter(|c| { c.win != w } ).collect(),
                 ..ws
-            }
+            })
         }).collect(),
         ..wm
     };
-    let ws = workspace::updateGeom(wm.wss.remove(wm.selwsindex), wm.drw.dpy);
-    wm.wss.insert(wm.selwsindex, ws);
-    wm
+    // Rearrange the visible workspaces
+    for i in 0..wm.mons.len() {
+        let wsindex = wm.mons[i].wsindex;
+        let ws = workspace::updateGeom(wm.wss.remove(wsindex), wm.drw.dpy);
+        wm.wss.insert(wsindex, ws);
+    }
+    focus(wm, None)
 }
 
 /**
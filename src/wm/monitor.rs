@@ -0,0 +1,77 @@
+extern crate x11;
+
+use std::slice;
+use std::os::raw::c_void;
+
+use x11::{ xlib, xinerama };
+
+use wm::workspace;
+use wm::workspace::BarCache;
+
+/**
+ * Stores a monitor : its geometry, its bar and the Workspace it shows
+ */
+pub struct Monitor {
+    pub num: usize,
+    pub x: i32, pub y: i32, pub w: u32, pub h: u32,
+    pub barwin: xlib::Window,
+    pub bar: BarCache,  // What is drawn on barwin
+    pub wsindex: usize
+}
+
+/**
+ * Checks that no screen of unique has the same geometry as info
+ */
+fn isUniqueGeom(unique: &[xinerama::XineramaScreenInfo], info: &xinerama::XineramaScreenInfo) -> bool {
+    !unique.iter().any(|u| {
+        u.x_org == info.x_org && u.y_org == info.y_org && u.width == info.width && u.height == info.height
+    })
+}
+
+/**
+ * Creates the monitors from the Xinerama screens (the first one being the primary monitor), or a
+ * single monitor covering the screen if Xinerama is not active
+ */
+pub fn createMonitors(dpy: &mut xlib::Display, sw: u32, sh: u32) -> Vec<Monitor> {
+    let mut unique: Vec<xinerama::XineramaScreenInfo> = Vec::new();
+    if unsafe { xinerama::XineramaIsActive(dpy) } != 0 {
+        let mut n = 0;
+        let info = unsafe { xinerama::XineramaQueryScreens(dpy, &mut n) };
+        if !info.is_null() {
+            for i in unsafe { slice::from_raw_parts(info, n as usize) }.iter() {
+                if isUniqueGeom(&unique, i) {
+                    unique.push(*i);
+                }
+            }
+            unsafe { xlib::XFree(info as *mut c_void) };
+        }
+    }
+    if unique.is_empty() {
+        return vec![Monitor { num: 0, x: 0, y: 0, w: sw, h: sh, barwin: 0, bar: workspace::createBarCache(), wsindex: 0 }];
+    }
+    unique.iter().enumerate().map(|(i, u)| {
+        Monitor {
+            num: i,
+            x: u.x_org as i32, y: u.y_org as i32, w: u.width as u32, h: u.height as u32,
+            barwin: 0,
+            bar: workspace::createBarCache(),
+            wsindex: i
+        }
+    }).collect()
+}
+
+/**
+ * Index of the monitor showing the Workspace wsindex, if it is visible
+ */
+pub fn showing(mons: &[Monitor], wsindex: usize) -> Option<usize> {
+    mons.iter().position(|m| { m.wsindex == wsindex })
+}
+
+/**
+ * Index of the monitor containing the point (x, y) (the first one if no monitor contains it)
+ */
+pub fn at(mons: &[Monitor], x: i32, y: i32) -> usize {
+    mons.iter().position(|m| {
+        x >= m.x && x < m.x + m.w as i32 && y >= m.y && y < m.y + m.h as i32
+    }).unwrap_or(0)
+}
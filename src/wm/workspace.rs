@@ -3,26 +3,29 @@ extern crate x11;
 use x11::xlib;
 
 use client;
-use { Client, Pertag };
+use wm::monitor::Monitor;
+use { Client, Pertag, Click };
 use { SCHEMENORM, SCHEMESEL };
 use drw;
 use drw::Drw;
 use drw::clrscheme::ClrScheme;
+use status::markup;
+use status::markup::Item;
 use config;
 
-/// Arrange functions
-pub fn tileArrange(mut ws: Workspace) -> Workspace {
-    let n = ws.clients.len() as u32;
-    let x = minX(&ws); let y = minY(&ws); let w = maxW(&ws); let h = maxH(&ws);
-    if n == 1 { // If there is only one window
+/// Arrange functions (floating Clients keep their geometry)
+pub fn tileArrange(ws: Workspace) -> Workspace {
+    let n = ws.clients.iter().filter(|c| { !c.isfloating }).count() as u32;
+    let (x, y, w, h) = layoutArea(&ws);
+    if let Some(w) = w.checked_div(n) {
+        let mut i = 0;
         Workspace {
-            clients: vec! [client::setGeom(ws.clients.remove(0), x, y, w, h)],
-            ..ws
-        }
-    } else if n > 1 {
-        let w = w/n;
-        Workspace {
-            clients: ws.clients.into_iter().enumerate().map(|(i, c)| { client::setGeom(c, x+(i as i32 * w as i32), y, w, h) }).collect(),
+            clients: ws.clients.into_iter().map(|c| {
+                if c.isfloating { return c }
+                let c = client::setGeom(c, x+(i * w as i32), y, w, h);
+                i += 1;
+                c
+            }).collect(),
             ..ws
         }
     } else {
@@ -67,8 +70,8 @@ pub struct Workspace<'a> {
     pub tagset: Vec<u32>,
     pub showbar: bool,
     pub topbar: bool,
-    pub clients: Vec<Client<'a>>,
-    pub barwin: xlib::Window,
+    pub clients: Vec<Client>,
+    pub sel: Option<xlib::Window>, // Selected client
     pub lt: Layout<'a>,
     pub pertag: Pertag<'a>
 }
@@ -96,7 +99,7 @@ pub fn createWorkspace<'a>(tag: &'a str) -> Workspace<'a> {
         showbar: config::showbar,
         topbar: config::topbar,
         clients: Vec::new(),
-        barwin: 0,
+        sel: None,
         lt: Layout { symbol: &config::layouts[0].symbol, arrange: config::layouts[0].arrange },
         pertag: Pertag {
             curtag: 1,
@@ -128,17 +131,56 @@ pub fn createWorkspace<'a>(tag: &'a str) -> Workspace<'a> {
 //     selmon
 // }
 
-pub fn minX(ws: &Workspace) -> i32 { ws.x }
+/**
+ * Selected Client of this Workspace
+ */
+pub fn selClient<'a>(ws: &'a Workspace) -> Option<&'a Client> {
+    match ws.sel {
+        Some(w) => ws.clients.iter().find(|c| c.win == w),
+        None => None
+    }
+}
 
-pub fn maxW(ws: &Workspace) -> u32 { ws.w }
+/**
+ * Makes sure the selected Client still belongs to this Workspace (selects the first Client otherwise)
+ */
+pub fn updateSel(ws: Workspace) -> Workspace {
+    let sel = match ws.sel {
+        Some(w) if ws.clients.iter().any(|c| c.win == w) => Some(w),
+        _ => ws.clients.first().map(|c| c.win)
+    };
+    Workspace { sel, ..ws }
+}
 
-pub fn minY(ws : &Workspace) -> i32 {
-    if ws.showbar && ws.topbar { ws.bh as i32} else { ws.y }
+/**
+ * Area of the Workspace left to the Clients by the bar : (x, y, w, h)
+ */
+pub fn layoutArea(ws: &Workspace) -> (i32, i32, u32, u32) {
+    if !ws.showbar {
+        return (ws.x, ws.y, ws.w, ws.h);
+    }
+    let h = ws.h.saturating_sub(ws.bh);
+    if ws.topbar { (ws.x, ws.y + ws.bh as i32, ws.w, h) } else { (ws.x, ws.y, ws.w, h) }
 }
 
-pub fn maxH(ws: &Workspace) -> u32 {
-    let m = if ws.showbar { ws.h - ws.bh as u32 } else { ws.h };
-    m
+pub fn minX(ws: &Workspace) -> i32 { layoutArea(ws).0 }
+
+pub fn minY(ws : &Workspace) -> i32 { layoutArea(ws).1 }
+
+pub fn maxW(ws: &Workspace) -> u32 { layoutArea(ws).2 }
+
+pub fn maxH(ws: &Workspace) -> u32 { layoutArea(ws).3 }
+
+/**
+ * Moves the Workspace to a monitor (Clients follow it), and updates the position of its bar
+ */
+pub fn moveTo<'a>(ws: Workspace<'a>, x: i32, y: i32, w: u32, h: u32, bh: u32) -> Workspace<'a> {
+    let (dx, dy) = (x - ws.x, y - ws.y);
+    updateBarPos(Workspace {
+        x, y, w, h,
+        clients: ws.clients.into_iter().map(|c| { Client { x: c.x + dx, y: c.y + dy, ..c } }).collect(),
+        ..ws
+    }, bh)
 }
 
 /**
@@ -147,7 +189,7 @@ pub fn maxH(ws: &Workspace) -> u32 {
 pub fn updateBarPos(ws: Workspace, bh: u32) -> Workspace {
     if ws.showbar {
         return Workspace {
-            by: if ws.topbar { ws.y } else { (ws.h as i32) - (bh as i32)},
+            by: if ws.topbar { ws.y } else { ws.y + ws.h as i32 - bh as i32 },
             bh,
             ..ws
         };
@@ -160,11 +202,158 @@ pub fn updateBarPos(ws: Workspace, bh: u32) -> Workspace {
 }
 
 /**
- * Draws the statusbar
+ * Width of a status text written with markup
+ */
+pub fn statusw<'a>(status: &[Item], drw: Drw<'a>) -> (Drw<'a>, u32) {
+    status.iter().fold((drw, 0), |(drw, w), item| {
+        match *item {
+            Item::Text(ref t) => {
+                let (drw, tw) = drw::textw(t, drw);
+                let h = drw.fonts[0].h;
+                (drw, w + tw - h)
+            },
+            Item::Forward(px) => (drw, (w as i32 + px).max(0) as u32),
+            Item::Icon(ref path) => {
+                let (drw, iw) = drw::iconw(path, drw);
+                (drw, w + iw)
+            },
+            _ => (drw, w)
+        }
+    })
+}
+
+/**
+ * Draws a status text written with markup, from x
+ */
+fn drawStatus<'a>(mut drw: Drw<'a>, mut x: i32, bh: u32, scheme: &[ClrScheme], status: &[Item]) -> Drw<'a> {
+    let mut cur = scheme[SCHEMENORM];
+    for item in status.iter() {
+        match *item {
+            Item::Text(ref t) => {
+                let (d, tw) = drw::textw(t, drw);
+                let w = tw - d.fonts[0].h;
+                let (d, _) = drw::text(drw::setScheme(d, &cur), x, 0, w, bh, 0, t, false);
+                drw = d;
+                x += w as i32;
+            },
+            Item::Fg(ref name) => {
+                let (d, c) = drw::getClr(drw, name);
+                drw = d;
+                if let Some(c) = c { cur.fg = c; }
+            },
+            Item::Bg(ref name) => {
+                let (d, c) = drw::getClr(drw, name);
+                drw = d;
+                if let Some(c) = c { cur.bg = c; }
+            },
+            Item::Scheme(ref name) => {
+                match &name[..] {
+                    "norm" => cur = scheme[SCHEMENORM],
+                    "sel" => cur = scheme[SCHEMESEL],
+                    _ => ()
+                }
+            },
+            Item::Reset => cur = scheme[SCHEMENORM],
+            Item::Rect(rx, ry, rw, rh) => {
+                if rw > 0 && rh > 0 {
+                    drw = drw::rect(drw::setScheme(drw, &cur), x + rx, ry, rw - 1, rh - 1, true, false);
+                }
+            },
+            Item::Forward(px) => x += px,
+            Item::Icon(ref path) => {
+                let (d, w) = drw::icon(drw::setScheme(drw, &cur), x, 0, bh, path);
+                drw = d;
+                x += w as i32;
+            }
+        }
+    }
+    // cur does not outlive this function, the colors it used can be freed
+    drw::trimCaches(drw::setScheme(drw, &scheme[SCHEMENORM]))
+}
+
+/**
+ * Finds the region of the bar of a monitor under x : the type of click, and the index of the tag for ClkTagBar
+ * or of the status block (separated by config::statusSeparator) for ClkStatusText
+ */
+pub fn barClick<'a>(mut drw: Drw<'a>, wss: &Vec<Workspace>, mon: &Monitor, stext: &str, status: Option<&Vec<Item>>, x: i32) -> (Drw<'a>, Click, Option<usize>) {
+    let mut tx = 0;
+    for (i, ws) in wss.iter().enumerate() {
+        let (d, w) = drw::textw(ws.tag, drw);
+        drw = d;
+        tx += w as i32;
+        if x < tx {
+            return (drw, Click::ClkTagBar, Some(i));
+        }
+    }
+    let (drw, w) = drw::textw(wss[mon.wsindex].lt.symbol, drw);
+    if x < tx + w as i32 {
+        return (drw, Click::ClkLtSymbol, None);
+    }
+    let status = match status {
+        Some(status) => status,
+        None => return (drw, Click::ClkWinTitle, None)
+    };
+    let lpad = drw.fonts[0].h / 2;
+    let (drw, stw) = statusw(status, drw);
+    let sx = mon.w as i32 - (stw + 2 * lpad) as i32;
+    if x >= sx {
+        let (drw, block) = statusBlock(drw, stext, x - sx - lpad as i32);
+        (drw, Click::ClkStatusText, block)
+    } else {
+        (drw, Click::ClkWinTitle, None)
+    }
+}
+
+/**
+ * Finds the block of the status text under x (relative to the beginning of the text)
+ */
+fn statusBlock<'a>(drw: Drw<'a>, stext: &str, x: i32) -> (Drw<'a>, Option<usize>) {
+    let (mut drw, dw) = drw::textw(config::statusSeparator, drw);
+    let dw = (dw - drw.fonts[0].h) as i32;
+    let mut bx = 0;
+    for (i, block) in stext.split(config::statusSeparator).enumerate() {
+        let (d, w) = statusw(&markup::parse(block), drw);
+        drw = d;
+        if x < bx {
+            return (drw, None); // On a separator
+        } else if x < bx + w as i32 {
+            return (drw, Some(i));
+        }
+        bx += w as i32 + dw;
+    }
+    (drw, None)
+}
+
+/// Title, floating and fixed state of a Client, as drawn on the bar
+type TitleCache = (String, bool, bool);
+
+/**
+ * Contents of the regions of a bar (with their position) as they were last drawn, so that only the
+ * regions which changed are drawn again
+ */
+#[derive(Clone, PartialEq)]
+pub struct BarCache {
+    tags: Option<(usize, Vec<bool>)>,  // Selected and occupied workspaces
+    ltsymbol: Option<(i32, String)>,
+    status: Option<(i32, Vec<Item>)>,
+    title: Option<(i32, i32, Option<TitleCache>, bool)>  // Selected client, selected monitor
+}
+
+/**
+ * Creates an empty cache : the whole bar will be drawn
  */
-pub fn drawBar<'a>(drw: Drw<'a>, bh: u32, scheme: &Vec<ClrScheme>, wss: &Vec<Workspace>, selmonindex: usize, stext: &str) -> Drw<'a> {
-    let w = drw.w;
-    let drw = drw::rect(drw::setScheme(drw, &scheme[SCHEMENORM]), 0, 0, w, bh, true, true);
+pub fn createBarCache() -> BarCache {
+    BarCache { tags: None, ltsymbol: None, status: None, title: None }
+}
+
+/**
+ * Draws the statusbar of a monitor : its Workspace, the title of its selected Client (highlighted on
+ * the selected monitor) and the status text if there is one. Only the regions which changed since
+ * the last drawing (see BarCache) are drawn and copied to the bar window.
+ */
+pub fn drawBar<'a>(drw: Drw<'a>, bh: u32, scheme: &[ClrScheme], wss: &[Workspace], mon: &Monitor, selected: bool, status: Option<&[Item]>) -> (Drw<'a>, BarCache) {
+    let bw = mon.w as i32;
+    let lpad = drw.fonts[0].h / 2;
     let dx: u32 = ((drw.fonts[0].ascent + drw.fonts[0].descent + 2) / 4) as u32;
     let occ = 0;
     let urg = 0;
@@ -174,41 +363,92 @@ pub fn drawBar<'a>(drw: Drw<'a>, bh: u32, scheme: &Vec<ClrScheme>, wss: &Vec<Wor
 //             urg = urg|c.tags
 //         }
 //     }
+    let old = &mon.bar;
 
     // Draw list of workspaces, with their tags
-    let (drw, _) = wss.iter().enumerate().fold((drw, 0), |(drw, x), (i, ws)| {
+    let tags = Some((mon.wsindex, wss.iter().map(|ws| { !ws.clients.is_empty() }).collect()));
+    let (drw, x) = wss.iter().fold((drw, 0), |(drw, x), ws| {
         let (drw, w) = drw::textw(ws.tag, drw);
-        let (drw, _) = drw::text(if i == selmonindex { drw::setScheme(drw, &scheme[SCHEMESEL]) }
-                                 else { drw::setScheme(drw, &scheme[SCHEMENORM]) },
-                                 x, 1, w, bh, ws.tag, urg & (1 << i) != 0);
-        let drw = if ws.clients.len() > 0 {
-            drw::rect(drw, x + 1, 1, dx, dx, i == selmonindex, occ & (1 << i) != 0)
-        }
-        else { drw };
         (drw, x + w as i32)
     });
+    let drw = if tags != old.tags {
+        let drw = drw::rect(drw::setScheme(drw, &scheme[SCHEMENORM]), 0, 0, x as u32, bh, true, true);
+        let (drw, _) = wss.iter().enumerate().fold((drw, 0), |(drw, x), (i, ws)| {
+            let (drw, w) = drw::textw(ws.tag, drw);
+            let (drw, _) = drw::text(if i == mon.wsindex { drw::setScheme(drw, &scheme[SCHEMESEL]) }
+                                     else { drw::setScheme(drw, &scheme[SCHEMENORM]) },
+                                     x, 1, w, bh, lpad, ws.tag, urg & (1 << i) != 0);
+            let drw = if !ws.clients.is_empty() {
+                drw::rect(drw, x + 1, 1, dx, dx, i == mon.wsindex, occ & (1 << i) != 0)
+            }
+            else { drw };
+            (drw, x + w as i32)
+        });
+        drw::mapWindow(drw, mon.barwin, 0, 0, x as u32, bh)
+    } else { drw };
+
+    // Layout symbol
+    let ws = &wss[mon.wsindex];
+    let (drw, w) = drw::textw(ws.lt.symbol, drw);
+    let ltsymbol = Some((x, ws.lt.symbol.to_string()));
+    let drw = if ltsymbol != old.ltsymbol {
+        let (drw, _) = drw::text(drw::setScheme(drw, &scheme[SCHEMENORM]), x, 0, w, bh, lpad, ws.lt.symbol, false);
+        drw::mapWindow(drw, mon.barwin, x, 0, w, bh)
+    } else { drw };
+    let x = x + w as i32;
 
     // Show status text on right of the bar
-    let (drw, w) = drw::textw(&stext, drw);
-    let bw = drw.w as i32;
-    let (drw, _) = drw::text(drw::setScheme(drw, &scheme[SCHEMENORM]), bw - (w as i32), 1, w, bh, &stext, false);
+    let (drw, stw, statuskey) = match status {
+        Some(status) => {
+            let (drw, stw) = statusw(status, drw);
+            let stw = stw + 2 * lpad;
+            (drw, stw, Some((bw - stw as i32, status.to_vec())))
+        },
+        None => (drw, 0, None)
+    };
+    let drw = match status {
+        Some(status) if statuskey != old.status => {
+            let drw = drw::rect(drw::setScheme(drw, &scheme[SCHEMENORM]), bw - (stw as i32), 0, stw, bh, true, true);
+            let drw = drawStatus(drw, bw - (stw as i32) + lpad as i32, bh, scheme, status);
+            drw::mapWindow(drw, mon.barwin, bw - stw as i32, 0, stw, bh)
+        },
+        _ => drw
+    };
 
-    // Map the window
-    let w = drw.w;
-    drw::mapWindow(drw, wss[selmonindex].barwin, 0, 0, w, bh) // C'est la que ca crashe : self.ww = 0 ?
+    // Title of the selected client in the remaining space
+    let w = bw - stw as i32 - x;
+    let title = Some((x, w, selClient(ws).map(|c| { (c.name.clone(), c.isfloating, c.isfixed) }), selected));
+    let drw = if title != old.title && w > 0 {
+        let w = w as u32;
+        let drw = match selClient(ws) {
+            Some(c) if w > bh => {
+                let titlescheme = if selected { &scheme[SCHEMESEL] } else { &scheme[SCHEMENORM] };
+                let (drw, _) = drw::text(drw::setScheme(drw, titlescheme), x, 0, w, bh, lpad, &c.name, false);
+                if c.isfloating {
+                    let boxs = drw.fonts[0].h / 9;
+                    let boxw = drw.fonts[0].h / 6 + 2;
+                    drw::rect(drw, x + boxs as i32, boxs as i32, boxw, boxw, c.isfixed, false)
+                } else { drw }
+            },
+            _ => drw::rect(drw::setScheme(drw, &scheme[SCHEMENORM]), x, 0, w, bh, true, true)
+        };
+        drw::mapWindow(drw, mon.barwin, x, 0, w, bh)
+    } else { drw };
+
+    (drw, BarCache { tags, ltsymbol, status: statuskey, title })
 }
 
 /**
  * Adds a Client to this Workspace
  */
-pub fn addClient<'a>(ws: &'a mut Workspace<'a>, c: Client<'a>) {
+pub fn addClient<'a>(ws: &'a mut Workspace<'a>, c: Client) {
     ws.clients.insert(0, c);
 }
 
 /**
  * Removes a Client from this Workspace, returning it
  */
-pub fn removeClient<'a>(ws: &mut Workspace<'a>, c: &Client<'a>) -> Option<Client<'a>> {
+pub fn removeClient<'a>(ws: &mut Workspace<'a>, c: &Client) -> Option<Client> {
     for i in 0..ws.clients.len() {
         if &ws.clients[i] == c {
             let cl = ws.clients.remove(i);
@@ -219,10 +459,17 @@ pub fn removeClient<'a>(ws: &mut Workspace<'a>, c: &Client<'a>) -> Option<Client
 }
 
 /**
- * Updates geometry of the Workspace
+ * Computes the geometry of the Clients of the Workspace, without moving their windows
  */
-pub fn updateGeom<'a>(ws: Workspace<'a>, dpy: &mut xlib::Display) -> Workspace<'a> {
+pub fn arrange<'a>(ws: Workspace<'a>) -> Workspace<'a> {
     let arrange = ws.lt.arrange;
+    arrange(updateSel(ws))
+}
+
+/**
+ * Updates geometry of the Workspace (its windows are moved on the screen)
+ */
+pub fn updateGeom<'a>(ws: Workspace<'a>, dpy: &mut xlib::Display) -> Workspace<'a> {
     let ws = arrange(ws);
     for c in ws.clients.iter() {
         client::configure(c, dpy);
@@ -233,13 +480,13 @@ pub fn updateGeom<'a>(ws: Workspace<'a>, dpy: &mut xlib::Display) -> Workspace<'
 /**
  * Draws all the windows in this workspace
  */
-pub fn showAllClients(ws: &Workspace, dpy: &mut xlib::Display) {
-    for c in ws.clients.iter() { client::show(c, dpy); }
+pub fn showAllClients(ws: &Workspace, dpy: &mut xlib::Display, wmatom: &Vec<xlib::Atom>) {
+    for c in ws.clients.iter() { client::show(c, dpy, wmatom); }
 }
 
 /**
- * Draws all the windows in this workspace
+ * Hides all the windows in this workspace
  */
-pub fn hideAllClients(ws: &Workspace, dpy: &mut xlib::Display) {
-    for c in ws.clients.iter() { client::hide(c, dpy); }
+pub fn hideAllClients(ws: &Workspace, dpy: &mut xlib::Display, wmatom: &Vec<xlib::Atom>) {
+    for c in ws.clients.iter() { client::hide(c, dpy, wmatom); }
 }